@@ -12,17 +12,105 @@ fn main() {
     println!("Input file: {}", file_name);
 
     let path = std::path::Path::new(&file_name);
-    if !path.exists() {
+    let is_url = file_name.contains("://");
+    if !is_url && !path.exists() {
         println!("ERROR: File not found.");
         return;
     }
 
     vtk::init();
-    let media_source = MediaSource::open(path);
+    let options = OpenOptions::new().timeout(std::time::Duration::from_secs(10));
+    let result = if is_url {
+        options.open_url(&file_name)
+    } else {
+        options.open(path)
+    };
+    let media_source = match result {
+        Ok(media_source) => media_source,
+        Err(e) => {
+            println!("ERROR: {}", e);
+            return;
+        }
+    };
     let format = media_source.format();
-    println!("Format: {}", format.name());
+    println!(
+        "Format: {} ({}), extensions: {:?}, MIME type: {}",
+        format.name(),
+        format.long_name(),
+        format.extensions(),
+        format.mime_type()
+    );
+    println!(
+        "Duration: {:?}, start time: {:?}, bit rate: {:?}",
+        media_source.duration(),
+        media_source.start_time(),
+        media_source.bit_rate()
+    );
+    for (key, value) in media_source.metadata() {
+        println!("  {}: {}", key, value);
+    }
+    for chapter in media_source.chapters() {
+        println!(
+            "Chapter {}: {:?} - {:?} {:?}",
+            chapter.id(),
+            chapter.start(),
+            chapter.end(),
+            chapter.title()
+        );
+    }
 
     media_source.all_streams().iter().for_each(|stream| {
-        println!("Stream {}: {:?}", stream.index(), stream.media_type());
+        println!(
+            "Stream {}: {:?} {} ({:?})",
+            stream.index(),
+            stream.media_type(),
+            stream.codec_name(),
+            stream.profile()
+        );
+        println!(
+            "  bit rate: {:?}, time base: {}, duration: {:?}, frames: {:?}, language: {:?}",
+            stream.bit_rate(),
+            stream.time_base(),
+            stream.duration(),
+            stream.frame_count(),
+            stream.language()
+        );
+        if let Some(video) = stream.video() {
+            println!(
+                "  {}x{} {:?}, SAR: {:?}, DAR: {:?}, frame rate: {:?} (real: {:?})",
+                video.width(),
+                video.height(),
+                video.pixel_format(),
+                video.sample_aspect_ratio(),
+                video.display_aspect_ratio(),
+                video.avg_frame_rate(),
+                video.real_frame_rate()
+            );
+            println!(
+                "  color: {:?}, {:?}, {:?}, {:?}",
+                video.color_space(),
+                video.color_range(),
+                video.color_primaries(),
+                video.color_transfer()
+            );
+            let orientation = video.orientation();
+            if orientation != Default::default() {
+                println!(
+                    "  rotation: {}, flip horizontal: {}, flip vertical: {}",
+                    orientation.rotation(),
+                    orientation.flip_horizontal(),
+                    orientation.flip_vertical()
+                );
+            }
+        }
+        if let Some(audio) = stream.audio() {
+            println!(
+                "  {} Hz, {} channels ({}), {:?}",
+                audio.sample_rate(),
+                audio.channels(),
+                audio.channel_layout(),
+                audio.sample_format()
+            );
+        }
     });
 }
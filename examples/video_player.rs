@@ -1,38 +1,73 @@
-use std::{
-    env,
-    sync::{mpsc, Arc},
+use std::{env, sync::Arc, time::Duration};
+use vtk::{
+    audio_decoder::AudioFormat,
+    audio_sink::NullSink,
+    player::{Player, PlayerEvent, PlayerState},
+    MediaSource,
 };
-use vtk::video_renderer::VideoRenderer;
 use winit::{
     application::ApplicationHandler,
-    event::WindowEvent,
+    event::{ElementState, WindowEvent},
     event_loop::{ActiveEventLoop, EventLoop, EventLoopProxy},
+    keyboard::{Key, NamedKey},
     window::{Window, WindowId},
 };
 
-extern crate ffmpeg_next as ffmpeg;
-type VideoReceiver = mpsc::Receiver<ffmpeg::frame::Video>;
+const SEEK_STEP: Duration = Duration::from_secs(10);
 
 #[derive(Debug, Clone, Copy)]
 enum UserEvent {
     RequestRedraw,
+    Player(PlayerEvent),
 }
 
 pub struct App {
     window: Option<Arc<Window>>,
-    renderer: VideoRenderer,
+    player: Player,
     event_loop_proxy: EventLoopProxy<UserEvent>,
 }
 
 impl App {
-    fn new(video_receiver: VideoReceiver, event_loop: &EventLoop<UserEvent>) -> Self {
+    fn new(mut player: Player, event_loop: &EventLoop<UserEvent>) -> Self {
         let event_loop_proxy = event_loop.create_proxy();
+        let proxy = event_loop_proxy.clone();
+        player.set_event_handler(Box::new(move |event| {
+            let _ = proxy.send_event(UserEvent::Player(event));
+        }));
         App {
             window: None,
-            renderer: VideoRenderer::new(video_receiver),
+            player,
             event_loop_proxy,
         }
     }
+
+    fn on_key(&mut self, key: Key) {
+        let result = match key.as_ref() {
+            Key::Named(NamedKey::Space) => match self.player.state() {
+                PlayerState::Playing => {
+                    self.player.pause();
+                    Ok(())
+                }
+                _ => self.player.play(),
+            },
+            Key::Character("s") => {
+                self.player.stop();
+                Ok(())
+            }
+            Key::Named(NamedKey::ArrowRight) => {
+                let position = self.player.position().unwrap_or_default();
+                self.player.seek(position + SEEK_STEP)
+            }
+            Key::Named(NamedKey::ArrowLeft) => {
+                let position = self.player.position().unwrap_or_default();
+                self.player.seek(position.saturating_sub(SEEK_STEP))
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            println!("ERROR: {}", e);
+        }
+    }
 }
 
 impl ApplicationHandler<UserEvent> for App {
@@ -43,6 +78,7 @@ impl ApplicationHandler<UserEvent> for App {
                     window.request_redraw();
                 }
             }
+            UserEvent::Player(event) => println!("Player event: {:?}", event),
         }
     }
 
@@ -53,13 +89,20 @@ impl ApplicationHandler<UserEvent> for App {
             let window = event_loop.create_window(attr).unwrap();
             let window = Arc::new(window);
             let proxy = self.event_loop_proxy.clone();
-            self.renderer.init(
+            if let Err(e) = self.player.init(
                 window.clone(),
                 Box::new(move || {
                     let _ = proxy.send_event(UserEvent::RequestRedraw);
                 }),
-            );
+            ) {
+                println!("ERROR: {}", e);
+                event_loop.exit();
+                return;
+            }
             self.window = Some(window);
+            if let Err(e) = self.player.play() {
+                println!("ERROR: {}", e);
+            }
         }
     }
 
@@ -72,13 +115,17 @@ impl ApplicationHandler<UserEvent> for App {
         match event {
             WindowEvent::Resized(size) => {
                 if let Some(window) = self.window.as_ref() {
-                    self.renderer.resize(size);
+                    self.player.resize(size);
                     window.request_redraw();
                 }
             }
             WindowEvent::RedrawRequested => {
-                self.renderer.render();
-                // self.window.as_ref().unwrap().request_redraw();
+                self.player.render();
+            }
+            WindowEvent::KeyboardInput { event, .. } => {
+                if event.state == ElementState::Pressed {
+                    self.on_key(event.logical_key);
+                }
             }
             WindowEvent::CloseRequested => {
                 println!("Close requested");
@@ -105,58 +152,26 @@ fn main() {
         return;
     }
 
-    ffmpeg::init().unwrap();
-
-    let mut ictx = ffmpeg::format::input(path).unwrap();
-    let input = ictx
-        .streams()
-        .best(ffmpeg::media::Type::Video)
-        .ok_or(ffmpeg::Error::StreamNotFound)
-        .unwrap();
-
-    let video_stream_index = input.index();
-
-    let context_decoder: ffmpeg::codec::Context =
-        ffmpeg::codec::context::Context::from_parameters(input.parameters()).unwrap();
-    let mut decoder = context_decoder.decoder().video().unwrap();
-    println!(
-        "\nVideo: {:?}, {}x{}",
-        decoder.id(),
-        decoder.width(),
-        decoder.height()
-    );
+    vtk::init();
 
-    let (packet_sender, packet_receiver) = mpsc::sync_channel(10);
-    let (video_sender, video_receiver) = mpsc::sync_channel(1);
-
-    std::thread::spawn(move || loop {
-        let mut packet = ffmpeg::codec::packet::Packet::empty();
-        if packet.read(&mut ictx).is_err() {
-            break;
-        }
-        if packet.stream() == video_stream_index {
-            packet_sender.send(packet).unwrap();
-        }
-    });
-
-    std::thread::spawn(move || loop {
-        let mut frame = ffmpeg::frame::Video::empty();
-        while unsafe { frame.is_empty() } {
-            if let Some(packet) = packet_receiver.recv().ok() {
-                decoder.send_packet(&packet).unwrap();
-                decoder.receive_frame(&mut frame).err();
-            } else {
-                break;
-            }
+    let media_source = match MediaSource::open(path) {
+        Ok(media_source) => media_source,
+        Err(e) => {
+            println!("ERROR: {}", e);
+            return;
         }
-        if unsafe { frame.is_empty() } {
-            break;
+    };
+    let audio_sink = NullSink::new(AudioFormat::default());
+    let player = match Player::new(media_source, Some(Box::new(audio_sink))) {
+        Ok(player) => player,
+        Err(e) => {
+            println!("ERROR: {}", e);
+            return;
         }
-        video_sender.send(frame).unwrap();
-    });
+    };
 
     let event_loop = EventLoop::<UserEvent>::with_user_event().build().unwrap();
-    let mut app = App::new(video_receiver, &event_loop);
+    let mut app = App::new(player, &event_loop);
 
     event_loop.run_app(&mut app).expect("Run app failed");
 }
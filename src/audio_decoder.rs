@@ -0,0 +1,307 @@
+use std::{ptr, time::Duration};
+
+extern crate ffmpeg_next as ffmpeg;
+use ffmpeg::{ffi::*, software::resampling, ChannelLayout, Rescale};
+
+use crate::{demuxer::DemuxerStream, MediaError, MediaType};
+
+/// Sample rate, channel layout and sample format the decoded audio is
+/// resampled to.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct AudioFormat {
+    pub sample_rate: u32,
+    pub channel_layout: ChannelLayout,
+    /// Type of the samples, and whether the channels are interleaved
+    /// (`Packed`) or each in a plane of its own (`Planar`).
+    pub sample: ffmpeg::format::Sample,
+}
+
+impl AudioFormat {
+    pub fn new(
+        sample_rate: u32,
+        channel_layout: ChannelLayout,
+        sample: ffmpeg::format::Sample,
+    ) -> Self {
+        AudioFormat {
+            sample_rate,
+            channel_layout,
+            sample,
+        }
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channel_layout.channels() as u16
+    }
+}
+
+// `ChannelLayout` wraps a raw `AVChannelLayout`, which only holds a pointer
+// for custom channel orders. Those are never allocated by this crate.
+unsafe impl Send for AudioFormat {}
+unsafe impl Sync for AudioFormat {}
+
+impl Default for AudioFormat {
+    /// 48 kHz stereo, interleaved `f32` samples.
+    fn default() -> Self {
+        AudioFormat::new(
+            48000,
+            ChannelLayout::STEREO,
+            ffmpeg::format::Sample::F32(ffmpeg::format::sample::Type::Packed),
+        )
+    }
+}
+
+/// A buffer of samples in the format of the `AudioFormat` it was decoded to,
+/// in native byte order.
+pub struct AudioBuffer {
+    pts: Option<i64>,
+    sample_rate: u32,
+    channels: u16,
+    sample: ffmpeg::format::Sample,
+    frames: usize,
+    data: Vec<u8>,
+}
+
+impl AudioBuffer {
+    pub(crate) fn new(
+        pts: Option<i64>,
+        sample_rate: u32,
+        channels: u16,
+        sample: ffmpeg::format::Sample,
+        frames: usize,
+        data: Vec<u8>,
+    ) -> Self {
+        AudioBuffer {
+            pts,
+            sample_rate,
+            channels,
+            sample,
+            frames,
+            data,
+        }
+    }
+
+    /// Presentation timestamp of the first sample, in the time base of the
+    /// stream.
+    pub fn pts(&self) -> Option<i64> {
+        self.pts
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    pub fn sample_format(&self) -> ffmpeg::format::Sample {
+        self.sample
+    }
+
+    /// The samples, interleaved for packed formats, one plane after the
+    /// other for planar ones.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The samples of channel `index` for planar formats, all of them for
+    /// packed ones, whose only plane is 0.
+    pub fn plane(&self, index: usize) -> &[u8] {
+        let size = self.data.len() / self.planes();
+        &self.data[index * size..(index + 1) * size]
+    }
+
+    pub fn planes(&self) -> usize {
+        if self.sample.is_planar() {
+            self.channels.max(1) as usize
+        } else {
+            1
+        }
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Number of samples per channel.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.frames() as f64 / self.sample_rate as f64)
+    }
+}
+
+/// Decodes the packets of an audio `DemuxerStream` and resamples them to the
+/// requested `AudioFormat`.
+pub struct AudioDecoder {
+    stream: DemuxerStream,
+    decoder: ffmpeg::decoder::Audio,
+    time_base: ffmpeg::Rational,
+    format: AudioFormat,
+    resampler: Option<resampling::Context>,
+    next_pts: Option<i64>,
+    state: crate::ffmpeg::DecodeState,
+}
+
+impl AudioDecoder {
+    pub fn new(stream: DemuxerStream, format: AudioFormat) -> Result<Self, MediaError> {
+        if stream.media_type() != MediaType::Audio || format.sample == ffmpeg::format::Sample::None
+        {
+            return Err(MediaError::UnsupportedFormat);
+        }
+
+        let time_base = stream.time_base();
+        let context = unsafe { crate::ffmpeg::new_codec_context(stream.as_ptr())? };
+        let mut decoder = context.decoder();
+        decoder.set_packet_time_base(time_base);
+        let decoder = decoder.audio()?;
+
+        Ok(AudioDecoder {
+            stream,
+            decoder,
+            time_base,
+            format,
+            resampler: None,
+            next_pts: None,
+            state: Default::default(),
+        })
+    }
+
+    pub fn stream(&self) -> &DemuxerStream {
+        &self.stream
+    }
+
+    pub fn time_base(&self) -> ffmpeg::Rational {
+        self.time_base
+    }
+
+    pub fn format(&self) -> AudioFormat {
+        self.format
+    }
+
+    /// Returns the next buffer of resampled audio, or `None` once the stream
+    /// has ended and the decoder and resampler have been drained.
+    pub fn decode(&mut self) -> Result<Option<AudioBuffer>, MediaError> {
+        let mut frame = ffmpeg::frame::Audio::empty();
+        while crate::ffmpeg::receive_frame(
+            &mut self.decoder,
+            &self.stream,
+            &mut frame,
+            &mut self.state,
+        )? {
+            if std::mem::take(&mut self.state.flushed) {
+                // Drop the samples buffered before the seek.
+                self.resampler = None;
+                self.next_pts = None;
+            }
+            let buffer = self.resample(&frame)?;
+            if buffer.frames() > 0 {
+                return Ok(Some(buffer));
+            }
+        }
+
+        let buffer = self.drain_resampler()?;
+        Ok(buffer.filter(|buffer| buffer.frames() > 0))
+    }
+
+    /// Discards the audio buffered in the decoder and resampler, e.g. after a
+    /// seek.
+    pub fn flush(&mut self) {
+        self.decoder.flush();
+        self.resampler = None;
+        self.next_pts = None;
+        self.state = Default::default();
+    }
+
+    fn resample(&mut self, frame: &ffmpeg::frame::Audio) -> Result<AudioBuffer, MediaError> {
+        let channel_layout = if frame.channel_layout().is_empty() {
+            ChannelLayout::default(frame.channels() as i32)
+        } else {
+            frame.channel_layout()
+        };
+        let input = resampling::Definition {
+            format: frame.format(),
+            channel_layout,
+            rate: frame.rate(),
+        };
+        if self.resampler.as_ref().map(|r| *r.input()) != Some(input) {
+            self.resampler = Some(resampling::Context::get(
+                input.format,
+                input.channel_layout,
+                input.rate,
+                self.format.sample,
+                self.format.channel_layout,
+                self.format.sample_rate,
+            )?);
+        }
+
+        if let Some(pts) = frame.timestamp() {
+            self.next_pts = Some(pts);
+        }
+        unsafe {
+            self.convert(
+                (*frame.as_ptr()).extended_data as *const *const u8,
+                frame.samples() as i32,
+            )
+        }
+    }
+
+    fn drain_resampler(&mut self) -> Result<Option<AudioBuffer>, MediaError> {
+        if self.resampler.is_none() {
+            return Ok(None);
+        }
+        unsafe { self.convert(ptr::null(), 0).map(Some) }
+    }
+
+    unsafe fn convert(
+        &mut self,
+        input: *const *const u8,
+        input_samples: i32,
+    ) -> Result<AudioBuffer, MediaError> {
+        let resampler = self.resampler.as_mut().unwrap();
+        let channels = self.format.channels();
+        let sample = self.format.sample;
+        let (planes, plane_channels) = if sample.is_planar() {
+            (channels as usize, 1)
+        } else {
+            (1, channels as usize)
+        };
+        let capacity = swr_get_out_samples(resampler.as_mut_ptr(), input_samples).max(0);
+        let plane_size = capacity as usize * plane_channels * sample.bytes();
+        let mut data = vec![0u8; plane_size * planes];
+        let mut output: Vec<*mut u8> = (0..planes)
+            .map(|i| data.as_mut_ptr().add(i * plane_size))
+            .collect();
+        let converted = match swr_convert(
+            resampler.as_mut_ptr(),
+            output.as_mut_ptr() as _,
+            capacity,
+            input as _,
+            input_samples,
+        ) {
+            e if e < 0 => return Err(e.into()),
+            n => n as usize,
+        };
+        // Moves the planes together, past the room left unused.
+        let size = converted * plane_channels * sample.bytes();
+        for i in 1..planes {
+            data.copy_within(i * plane_size..i * plane_size + size, i * size);
+        }
+        data.truncate(size * planes);
+
+        let pts = self.next_pts;
+        self.next_pts = pts.map(|pts| {
+            pts + (converted as i64).rescale((1, self.format.sample_rate as i32), self.time_base)
+        });
+        Ok(AudioBuffer::new(
+            pts,
+            self.format.sample_rate,
+            channels,
+            sample,
+            converted,
+            data,
+        ))
+    }
+}
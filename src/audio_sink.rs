@@ -0,0 +1,256 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+extern crate ffmpeg_next as ffmpeg;
+use ffmpeg::format::{sample::Type, Sample};
+
+use crate::audio_decoder::{AudioBuffer, AudioFormat};
+
+/// Destination of decoded audio, e.g. a sound device.
+pub trait AudioSink: Send {
+    /// The format of the buffers accepted by `write`.
+    fn format(&self) -> AudioFormat;
+
+    /// Queues a buffer for playback. Blocks while the sink has no room for
+    /// it, so that a decoding thread is paced by the sink.
+    fn write(&mut self, buffer: &AudioBuffer) -> io::Result<()>;
+
+    /// Duration of the audio played so far.
+    fn position(&self) -> Duration;
+
+    /// Makes sure everything written so far has been delivered.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Suspends the playback of the audio written so far.
+    fn pause(&mut self) {}
+
+    fn resume(&mut self) {}
+}
+
+fn check_format(format: &AudioFormat, buffer: &AudioBuffer) -> io::Result<()> {
+    if buffer.sample_rate() != format.sample_rate
+        || buffer.channels() != format.channels()
+        || buffer.sample_format() != format.sample
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Audio buffer does not match the format of the sink",
+        ));
+    }
+    Ok(())
+}
+
+/// A sink that discards the audio, but consumes it at real-time pace.
+pub struct NullSink {
+    format: AudioFormat,
+    start: Option<Instant>,
+    paused_at: Option<Instant>,
+    written: Duration,
+}
+
+impl NullSink {
+    pub fn new(format: AudioFormat) -> Self {
+        NullSink {
+            format,
+            start: None,
+            paused_at: None,
+            written: Duration::ZERO,
+        }
+    }
+}
+
+impl AudioSink for NullSink {
+    fn format(&self) -> AudioFormat {
+        self.format
+    }
+
+    fn write(&mut self, buffer: &AudioBuffer) -> io::Result<()> {
+        check_format(&self.format, buffer)?;
+        self.resume();
+        let start = *self.start.get_or_insert_with(Instant::now);
+        // The buffer starts playing once the previous ones have been played.
+        let due = start + self.written;
+        let now = Instant::now();
+        if due > now {
+            thread::sleep(due - now);
+        }
+        self.written += buffer.duration();
+        Ok(())
+    }
+
+    /// Blocks until the audio written so far has been played.
+    fn flush(&mut self) -> io::Result<()> {
+        self.resume();
+        if let Some(start) = self.start {
+            let due = start + self.written;
+            let now = Instant::now();
+            if due > now {
+                thread::sleep(due - now);
+            }
+        }
+        Ok(())
+    }
+
+    fn position(&self) -> Duration {
+        match (self.start, self.paused_at) {
+            (Some(start), Some(paused_at)) => (paused_at - start).min(self.written),
+            (Some(start), None) => start.elapsed().min(self.written),
+            (None, _) => Duration::ZERO,
+        }
+    }
+
+    fn pause(&mut self) {
+        if self.paused_at.is_none() {
+            self.paused_at = Some(Instant::now());
+        }
+    }
+
+    fn resume(&mut self) {
+        if let (Some(start), Some(paused_at)) = (self.start.as_mut(), self.paused_at.take()) {
+            *start += paused_at.elapsed();
+        }
+    }
+}
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const WAV_HEADER_SIZE: u64 = 12 + 8 + 16 + 8 + 4 + 8;
+// Largest data size for which the RIFF size still fits in 32 bits.
+const MAX_WAV_DATA_SIZE: u64 = u32::MAX as u64 - (WAV_HEADER_SIZE - 8);
+
+/// A sink that writes the audio to a WAV file, as fast as it is written. The
+/// format must have interleaved samples, of unsigned 8-bit, signed 16 or
+/// 32-bit, or float type.
+pub struct WavFileSink {
+    writer: BufWriter<File>,
+    format: AudioFormat,
+    data_size: u64,
+    // Set once `finish` has written the final header.
+    finished: bool,
+}
+
+impl WavFileSink {
+    pub fn create(path: &Path, format: AudioFormat) -> io::Result<Self> {
+        if wav_format_tag(format.sample).is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Sample format not supported by WAV files",
+            ));
+        }
+        let mut sink = WavFileSink {
+            writer: BufWriter::new(File::create(path)?),
+            format,
+            data_size: 0,
+            finished: false,
+        };
+        sink.write_header()?;
+        Ok(sink)
+    }
+
+    /// Completes the header of the file and closes it.
+    pub fn finish(mut self) -> io::Result<()> {
+        let result = self.flush();
+        self.finished = true;
+        result
+    }
+
+    /// Writes the header at the current position. Files of more than 4 GiB
+    /// get the largest sizes the header can hold, which most readers take as
+    /// "up to the end of the file".
+    fn write_header(&mut self) -> io::Result<()> {
+        if self.data_size > MAX_WAV_DATA_SIZE {
+            log::warn!(
+                "WAV data of {} bytes exceeds the 4 GiB limit of the header, its sizes are clamped",
+                self.data_size
+            );
+        }
+        let data_size = self.data_size.min(MAX_WAV_DATA_SIZE);
+        let channels = self.format.channels();
+        let bytes = self.format.sample.bytes() as u32;
+        let block_align = channels as u32 * bytes;
+        let frames = data_size / block_align.max(1) as u64;
+        let w = &mut self.writer;
+        w.write_all(b"RIFF")?;
+        w.write_all(&((WAV_HEADER_SIZE - 8 + data_size) as u32).to_le_bytes())?;
+        w.write_all(b"WAVE")?;
+
+        w.write_all(b"fmt ")?;
+        w.write_all(&16u32.to_le_bytes())?;
+        w.write_all(&wav_format_tag(self.format.sample).unwrap().to_le_bytes())?;
+        w.write_all(&channels.to_le_bytes())?;
+        w.write_all(&self.format.sample_rate.to_le_bytes())?;
+        w.write_all(&(self.format.sample_rate * block_align).to_le_bytes())?;
+        w.write_all(&(block_align as u16).to_le_bytes())?;
+        w.write_all(&(bytes as u16 * 8).to_le_bytes())?;
+
+        w.write_all(b"fact")?;
+        w.write_all(&4u32.to_le_bytes())?;
+        w.write_all(&(frames as u32).to_le_bytes())?;
+
+        w.write_all(b"data")?;
+        w.write_all(&(data_size as u32).to_le_bytes())?;
+        Ok(())
+    }
+}
+
+impl AudioSink for WavFileSink {
+    fn format(&self) -> AudioFormat {
+        self.format
+    }
+
+    fn write(&mut self, buffer: &AudioBuffer) -> io::Result<()> {
+        check_format(&self.format, buffer)?;
+        if cfg!(target_endian = "little") {
+            self.writer.write_all(buffer.data())?;
+        } else {
+            // WAV files are little-endian.
+            for sample in buffer.data().chunks(self.format.sample.bytes()) {
+                let mut sample = sample.to_vec();
+                sample.reverse();
+                self.writer.write_all(&sample)?;
+            }
+        }
+        self.data_size += buffer.data().len() as u64;
+        Ok(())
+    }
+
+    fn position(&self) -> Duration {
+        let bytes_per_second = self.format.sample_rate as u64
+            * self.format.channels() as u64
+            * self.format.sample.bytes() as u64;
+        Duration::from_secs_f64(self.data_size as f64 / bytes_per_second.max(1) as f64)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()
+    }
+}
+
+/// Format tag of WAV files holding `sample`, `None` if they can not.
+fn wav_format_tag(sample: Sample) -> Option<u16> {
+    match sample {
+        Sample::U8(Type::Packed) | Sample::I16(Type::Packed) | Sample::I32(Type::Packed) => {
+            Some(WAVE_FORMAT_PCM)
+        }
+        Sample::F32(Type::Packed) | Sample::F64(Type::Packed) => Some(WAVE_FORMAT_IEEE_FLOAT),
+        _ => None,
+    }
+}
+
+impl Drop for WavFileSink {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.flush();
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+extern crate ffmpeg_next as ffmpeg;
+
+// Frames that are early by less than this are presented right away.
+const EARLY_TOLERANCE: Duration = Duration::from_millis(2);
+// Frames that are late by more than this are dropped.
+const MAX_LATENESS: Duration = Duration::from_millis(40);
+// How often a held frame checks the clock again, which may have been paused,
+// set or started in the meantime.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Which stream drives the presentation clock.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum ClockMode {
+    /// The clock follows the audio played by the sink. Video frames are held
+    /// or dropped to stay in sync with it.
+    AudioMaster,
+    /// The clock follows the presented video frames. Late frames are shown
+    /// anyway and the clock is set back to them, so nothing is dropped.
+    VideoMaster,
+    /// The clock runs freely on the system clock from the first frame on.
+    ExternalClock,
+}
+
+struct ClockState {
+    // Media time at `anchor`, `None` until the clock has been started.
+    time: Option<Duration>,
+    anchor: Instant,
+    paused: bool,
+    // Set once the audio will not set the clock anymore.
+    audio_ended: bool,
+}
+
+/// A presentation clock shared by the audio and video pipelines.
+#[derive(Clone)]
+pub struct Clock {
+    mode: ClockMode,
+    state: Arc<Mutex<ClockState>>,
+}
+
+impl Clock {
+    pub fn new(mode: ClockMode) -> Self {
+        Clock {
+            mode,
+            state: Arc::new(Mutex::new(ClockState {
+                time: None,
+                anchor: Instant::now(),
+                paused: false,
+                audio_ended: false,
+            })),
+        }
+    }
+
+    pub fn mode(&self) -> ClockMode {
+        self.mode
+    }
+
+    /// Current media time, or `None` if the clock has not been started.
+    pub fn time(&self) -> Option<Duration> {
+        let state = self.state.lock().unwrap();
+        state.time.map(|time| {
+            if state.paused {
+                time
+            } else {
+                time + state.anchor.elapsed()
+            }
+        })
+    }
+
+    /// Sets the current media time, starting the clock if needed.
+    pub fn set(&self, time: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.time = Some(time);
+        state.anchor = Instant::now();
+    }
+
+    /// Stops the clock until it is set again, e.g. after a seek.
+    pub fn reset(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.time = None;
+        state.audio_ended = false;
+    }
+
+    /// Tells that the audio reached its end or failed, so that it will not
+    /// set the clock anymore until the next `reset`. In `AudioMaster` mode,
+    /// the video then starts the clock itself.
+    pub fn end_audio(&self) {
+        self.state.lock().unwrap().audio_ended = true;
+    }
+
+    pub fn is_audio_ended(&self) -> bool {
+        self.state.lock().unwrap().audio_ended
+    }
+
+    pub fn pause(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !state.paused {
+            if let Some(time) = state.time {
+                state.time = Some(time + state.anchor.elapsed());
+            }
+            state.paused = true;
+        }
+    }
+
+    pub fn resume(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.paused {
+            state.anchor = Instant::now();
+            state.paused = false;
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().paused
+    }
+}
+
+/// Converts a timestamp in `time_base` to a media time. Negative timestamps
+/// are clamped to zero.
+pub(crate) fn pts_to_duration(pts: i64, time_base: ffmpeg::Rational) -> Duration {
+    Duration::from_secs_f64((pts as f64 * f64::from(time_base)).max(0.0))
+}
+
+pub(crate) enum Schedule {
+    Present,
+    Drop,
+    Wait(Duration),
+}
+
+/// Decides when video frames are presented on a `Clock`.
+pub(crate) struct FrameScheduler {
+    clock: Clock,
+    time_base: ffmpeg::Rational,
+}
+
+impl FrameScheduler {
+    pub fn new(clock: Clock, time_base: ffmpeg::Rational) -> Self {
+        FrameScheduler { clock, time_base }
+    }
+
+    /// Checks a frame with the given PTS against the clock. A frame that is
+    /// due is presented, unless it is too late, and a frame that is early has
+    /// to wait at most the returned duration before being checked again.
+    pub fn check(&self, pts: Option<i64>) -> Schedule {
+        let Some(pts) = pts else {
+            return Schedule::Present;
+        };
+        let time = pts_to_duration(pts, self.time_base);
+
+        let now = match (self.clock.time(), self.clock.mode()) {
+            (Some(now), _) => now,
+            // Wait for the audio to start the clock, unless it has no more
+            // data, e.g. after a seek past its end.
+            (None, ClockMode::AudioMaster) if !self.clock.is_audio_ended() => {
+                return Schedule::Wait(POLL_INTERVAL)
+            }
+            (None, _) => {
+                self.clock.set(time);
+                return Schedule::Present;
+            }
+        };
+
+        if time > now + EARLY_TOLERANCE {
+            return Schedule::Wait((time - now).min(POLL_INTERVAL));
+        }
+
+        match self.clock.mode() {
+            ClockMode::VideoMaster => {
+                self.clock.set(time);
+                Schedule::Present
+            }
+            _ if now.saturating_sub(time) > MAX_LATENESS => Schedule::Drop,
+            _ => Schedule::Present,
+        }
+    }
+
+    /// Blocks until a frame with the given PTS is due. Returns `false` if the
+    /// frame is too late and should be dropped.
+    pub fn wait_until_due(&self, pts: Option<i64>) -> bool {
+        loop {
+            match self.check(pts) {
+                Schedule::Present => return true,
+                Schedule::Drop => return false,
+                Schedule::Wait(duration) => thread::sleep(duration),
+            }
+        }
+    }
+}
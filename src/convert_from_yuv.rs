@@ -3,6 +3,7 @@ use std::rc::Rc;
 use wgpu::util::DeviceExt;
 extern crate ffmpeg_next as ffmpeg;
 
+use crate::uploader::{Plane, Uploader};
 use crate::{texture::Texture, wgpu_context::WgpuContext};
 
 #[repr(C)]
@@ -66,6 +67,13 @@ enum ColorMatrix {
 }
 
 impl ColorMatrix {
+    fn is_full_range(&self) -> bool {
+        matches!(
+            self,
+            ColorMatrix::BT601Full | ColorMatrix::BT709Full | ColorMatrix::BT2020Full
+        )
+    }
+
     fn index(&self) -> u32 {
         match self {
             ColorMatrix::BT601Limited => 0,
@@ -115,14 +123,331 @@ fn matrix_index(
     }
 }
 
+#[derive(Clone, Copy, PartialEq)]
+enum ChromaLayout {
+    /// U and V in their own planes.
+    Planar,
+    /// U and V interleaved in a single plane.
+    Uv,
+    Vu,
+}
+
+impl ChromaLayout {
+    fn index(&self) -> u32 {
+        match self {
+            ChromaLayout::Planar => 0,
+            ChromaLayout::Uv => 1,
+            ChromaLayout::Vu => 2,
+        }
+    }
+}
+
+/// How the samples of a supported format are stored.
+#[derive(Clone, Copy)]
+struct FormatLayout {
+    chroma: ChromaLayout,
+    /// Significant bits per sample.
+    bits: u32,
+    /// Bits the samples are shifted left by within their 16-bit word.
+    shift: u32,
+}
+
+impl FormatLayout {
+    /// FFmpeg has no high bit depth variants of the 4:1:0 and 4:1:1 formats.
+    fn of(format: ffmpeg::format::Pixel) -> Option<Self> {
+        let (chroma, bits, shift) = match format {
+            ffmpeg::format::Pixel::YUV410P
+            | ffmpeg::format::Pixel::YUV411P
+            | ffmpeg::format::Pixel::YUVJ411P
+            | ffmpeg::format::Pixel::YUV420P
+            | ffmpeg::format::Pixel::YUVJ420P
+            | ffmpeg::format::Pixel::YUV422P
+            | ffmpeg::format::Pixel::YUVJ422P
+            | ffmpeg::format::Pixel::YUV440P
+            | ffmpeg::format::Pixel::YUVJ440P
+            | ffmpeg::format::Pixel::YUV444P
+            | ffmpeg::format::Pixel::YUVJ444P => (ChromaLayout::Planar, 8, 0),
+            ffmpeg::format::Pixel::NV12 => (ChromaLayout::Uv, 8, 0),
+            ffmpeg::format::Pixel::NV21 => (ChromaLayout::Vu, 8, 0),
+            ffmpeg::format::Pixel::YUV420P10LE
+            | ffmpeg::format::Pixel::YUV422P10LE
+            | ffmpeg::format::Pixel::YUV440P10LE
+            | ffmpeg::format::Pixel::YUV444P10LE => (ChromaLayout::Planar, 10, 0),
+            ffmpeg::format::Pixel::YUV420P12LE
+            | ffmpeg::format::Pixel::YUV422P12LE
+            | ffmpeg::format::Pixel::YUV440P12LE
+            | ffmpeg::format::Pixel::YUV444P12LE => (ChromaLayout::Planar, 12, 0),
+            ffmpeg::format::Pixel::YUV420P16LE
+            | ffmpeg::format::Pixel::YUV422P16LE
+            | ffmpeg::format::Pixel::YUV444P16LE => (ChromaLayout::Planar, 16, 0),
+            ffmpeg::format::Pixel::P010LE => (ChromaLayout::Uv, 10, 6),
+            ffmpeg::format::Pixel::P016LE => (ChromaLayout::Uv, 16, 0),
+            _ => return None,
+        };
+        Some(FormatLayout {
+            chroma,
+            bits,
+            shift,
+        })
+    }
+
+    fn plane_count(&self) -> usize {
+        match self.chroma {
+            ChromaLayout::Planar => 3,
+            _ => 2,
+        }
+    }
+
+    fn is_16bit(&self) -> bool {
+        self.bits > 8
+    }
+
+    fn texture_format(&self, plane: usize) -> wgpu::TextureFormat {
+        match (plane, self.chroma, self.is_16bit()) {
+            (1, ChromaLayout::Uv | ChromaLayout::Vu, false) => wgpu::TextureFormat::Rg8Unorm,
+            (1, ChromaLayout::Uv | ChromaLayout::Vu, true) => wgpu::TextureFormat::Rg16Unorm,
+            (_, _, false) => wgpu::TextureFormat::R8Unorm,
+            (_, _, true) => wgpu::TextureFormat::R16Unorm,
+        }
+    }
+
+    /// Parameters bringing the sampled values to the 8-bit scale the color
+    /// matrices are expressed in. Limited range samples scale with the bit
+    /// depth, full range ones span the whole range of their bit depth.
+    fn params(&self, matrix: &ColorMatrix) -> Params {
+        let texel_max = if self.is_16bit() { 65535.0 } else { 255.0 };
+        let shift = (1u32 << self.shift) as f32;
+        let max = ((1u64 << self.bits) - 1) as f32;
+        let (scale, y_offset, uv_offset) = if matrix.is_full_range() {
+            let uv_offset = (1u32 << (self.bits - 1)) as f32 / max;
+            (texel_max / (shift * max), 0.0, uv_offset)
+        } else {
+            let depth = (1u32 << (self.bits - 8)) as f32;
+            (
+                texel_max / (shift * 255.0 * depth),
+                16.0 / 255.0,
+                128.0 / 255.0,
+            )
+        };
+        Params {
+            crop_offset: [0.0; 2],
+            crop_scale: [1.0; 2],
+            matrix_index: matrix.index(),
+            chroma_layout: self.chroma.index(),
+            scale,
+            y_offset,
+            uv_offset,
+            _padding: [0; 3],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    crop_offset: [f32; 2],
+    crop_scale: [f32; 2],
+    matrix_index: u32,
+    chroma_layout: u32,
+    scale: f32,
+    y_offset: f32,
+    uv_offset: f32,
+    _padding: [u32; 3],
+}
+
+/// GPU resources for the frames of a given format and size, reused from
+/// frame to frame.
+struct Planes {
+    key: (ffmpeg::format::Pixel, u32, u32),
+    textures: Vec<Texture>,
+    params: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+/// How `YuvToRgbaConverter` writes the converted frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConversionMethod {
+    /// Compute shader if the output texture can not be rendered to, render
+    /// pass otherwise.
+    #[default]
+    Auto,
+    /// Draws a quad into the output texture, which needs the
+    /// `RENDER_ATTACHMENT` usage.
+    RenderPass,
+    /// Writes the output texture from a compute shader, which needs the
+    /// `STORAGE_BINDING` usage. Falls back to the render pass on devices
+    /// without compute shaders or storage textures.
+    Compute,
+}
+
+/// Compute pipelines, on devices supporting them.
+struct ComputePipelines {
+    pipeline: wgpu::ComputePipeline,
+    output_layout: wgpu::BindGroupLayout,
+    // Writes `Rgba16Unorm` textures, for high bit depth frames, on devices
+    // with 16-bit normalized formats.
+    pipeline_16: Option<(wgpu::ComputePipeline, wgpu::BindGroupLayout)>,
+    // Bind group of the last output texture.
+    output: Option<(wgpu::Id<wgpu::TextureView>, wgpu::BindGroup)>,
+}
+
+impl ComputePipelines {
+    fn new(context: &WgpuContext, bind_group_layout: &wgpu::BindGroupLayout) -> Option<Self> {
+        let downlevel = context.adapter.get_downlevel_capabilities();
+        if !downlevel
+            .flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+            || context
+                .device
+                .limits()
+                .max_storage_textures_per_shader_stage
+                == 0
+        {
+            return None;
+        }
+
+        // The 16-bit storage format is only valid in shaders of devices
+        // supporting it.
+        let high_depth = context
+            .device
+            .features()
+            .contains(wgpu::Features::TEXTURE_FORMAT_16BIT_NORM);
+        let mut source = String::from(concat!(
+            include_str!("shaders/convert_yuv_to_rgba.wgsl"),
+            include_str!("shaders/convert_yuv_to_rgba_compute.wgsl"),
+        ));
+        if high_depth {
+            source.push_str(include_str!("shaders/convert_yuv_to_rgba_compute_16.wgsl"));
+        }
+        let shader = context
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            });
+
+        let create_pipeline = |binding: u32, format: wgpu::TextureFormat, entry_point: &str| {
+            let output_layout =
+                context
+                    .device
+                    .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                        entries: &[wgpu::BindGroupLayoutEntry {
+                            binding,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::StorageTexture {
+                                access: wgpu::StorageTextureAccess::WriteOnly,
+                                format,
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                            },
+                            count: None,
+                        }],
+                        label: Some("yuv_output_bind_group_layout"),
+                    });
+            let pipeline_layout =
+                context
+                    .device
+                    .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                        label: None,
+                        bind_group_layouts: &[bind_group_layout, &output_layout],
+                        push_constant_ranges: &[],
+                    });
+            let pipeline =
+                context
+                    .device
+                    .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: None,
+                        layout: Some(&pipeline_layout),
+                        module: &shader,
+                        entry_point,
+                        compilation_options: Default::default(),
+                        cache: None,
+                    });
+            (pipeline, output_layout)
+        };
+        let (pipeline, output_layout) =
+            create_pipeline(0, wgpu::TextureFormat::Rgba8Unorm, "cs_main");
+        let pipeline_16 =
+            high_depth.then(|| create_pipeline(1, wgpu::TextureFormat::Rgba16Unorm, "cs_main_16"));
+
+        Some(Self {
+            pipeline,
+            output_layout,
+            pipeline_16,
+            output: None,
+        })
+    }
+
+    /// Dispatches the conversion of the frame bound by `bind_group` into
+    /// `dst`, resampled to the size of `dst`.
+    fn dispatch(
+        &mut self,
+        context: &WgpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        bind_group: &wgpu::BindGroup,
+        dst: &Texture,
+    ) {
+        let (pipeline, layout, binding) = match (dst.texture.format(), &self.pipeline_16) {
+            (wgpu::TextureFormat::Rgba16Unorm, Some((pipeline, layout))) => (pipeline, layout, 1),
+            _ => (&self.pipeline, &self.output_layout, 0),
+        };
+        let id = dst.view.global_id();
+        let output = match self.output.take() {
+            Some(output) if output.0 == id => output,
+            _ => {
+                let bind_group = context
+                    .device
+                    .create_bind_group(&wgpu::BindGroupDescriptor {
+                        layout,
+                        entries: &[wgpu::BindGroupEntry {
+                            binding,
+                            resource: wgpu::BindingResource::TextureView(&dst.view),
+                        }],
+                        label: Some("yuv_output_bind_group"),
+                    });
+                (id, bind_group)
+            }
+        };
+        let output_bind_group = &self.output.insert(output).1;
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: None,
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(pipeline);
+        compute_pass.set_bind_group(0, bind_group, &[]);
+        compute_pass.set_bind_group(1, output_bind_group, &[]);
+        let size = dst.texture.size();
+        compute_pass.dispatch_workgroups(size.width.div_ceil(8), size.height.div_ceil(8), 1);
+    }
+}
+
+/// Whether the device can render into `Rgba16Unorm` textures, which
+/// adapters may support beyond the guaranteed storage usage.
+fn can_render_16bit(context: &WgpuContext) -> bool {
+    context
+        .device
+        .features()
+        .contains(wgpu::Features::TEXTURE_FORMAT_16BIT_NORM)
+        && context
+            .format_usages(wgpu::TextureFormat::Rgba16Unorm)
+            .contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+}
+
 pub(crate) struct YuvToRgbaConverter {
     context: Rc<WgpuContext>,
     pipeline: wgpu::RenderPipeline,
+    // Render into the textures of high bit depth frames, `Rgba16Unorm` ones
+    // on devices that can render to them, `Rgba16Float` ones otherwise.
+    pipeline_16: Option<wgpu::RenderPipeline>,
+    pipeline_16f: wgpu::RenderPipeline,
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
     num_indices: u32,
     bind_group_layout: wgpu::BindGroupLayout,
     sampler: wgpu::Sampler,
+    planes: Option<Planes>,
+    compute: Option<ComputePipelines>,
+    method: ConversionMethod,
 }
 
 impl YuvToRgbaConverter {
@@ -155,7 +480,7 @@ impl YuvToRgbaConverter {
 
         let y_planner = wgpu::BindGroupLayoutEntry {
             binding: 0,
-            visibility: wgpu::ShaderStages::FRAGMENT,
+            visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
             ty: wgpu::BindingType::Texture {
                 multisampled: false,
                 view_dimension: wgpu::TextureViewDimension::D2,
@@ -173,13 +498,13 @@ impl YuvToRgbaConverter {
         };
         let sampler = wgpu::BindGroupLayoutEntry {
             binding: 3,
-            visibility: wgpu::ShaderStages::FRAGMENT,
+            visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
             ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
             count: None,
         };
         let matrix = wgpu::BindGroupLayoutEntry {
             binding: 4,
-            visibility: wgpu::ShaderStages::FRAGMENT,
+            visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
             ty: wgpu::BindingType::Buffer {
                 ty: wgpu::BufferBindingType::Uniform,
                 has_dynamic_offset: false,
@@ -205,45 +530,51 @@ impl YuvToRgbaConverter {
                     push_constant_ranges: &[],
                 });
 
-        let pipeline = context
-            .device
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: None,
-                layout: Some(&render_pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &shader,
-                    compilation_options: Default::default(),
-                    entry_point: "vs_main",
-                    buffers: &[Vertex::desc()],
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: &shader,
-                    compilation_options: Default::default(),
-                    entry_point: "fs_main",
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: wgpu::TextureFormat::Rgba8Unorm,
-                        blend: Some(wgpu::BlendState::REPLACE),
-                        write_mask: wgpu::ColorWrites::ALL,
-                    })],
-                }),
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList,
-                    strip_index_format: None,
-                    front_face: wgpu::FrontFace::Ccw,
-                    cull_mode: Some(wgpu::Face::Back),
-                    polygon_mode: wgpu::PolygonMode::Fill,
-                    unclipped_depth: false,
-                    conservative: false,
-                },
-                depth_stencil: None,
-                multisample: wgpu::MultisampleState {
-                    count: 1,
-                    mask: !0,
-                    alpha_to_coverage_enabled: false,
-                },
-                multiview: None,
-                cache: None,
-            });
+        let create_pipeline = |format: wgpu::TextureFormat| {
+            context
+                .device
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: None,
+                    layout: Some(&render_pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        compilation_options: Default::default(),
+                        entry_point: "vs_main",
+                        buffers: &[Vertex::desc()],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        compilation_options: Default::default(),
+                        entry_point: "fs_main",
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format,
+                            blend: Some(wgpu::BlendState::REPLACE),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        strip_index_format: None,
+                        front_face: wgpu::FrontFace::Ccw,
+                        cull_mode: Some(wgpu::Face::Back),
+                        polygon_mode: wgpu::PolygonMode::Fill,
+                        unclipped_depth: false,
+                        conservative: false,
+                    },
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState {
+                        count: 1,
+                        mask: !0,
+                        alpha_to_coverage_enabled: false,
+                    },
+                    multiview: None,
+                    cache: None,
+                })
+        };
+        let pipeline = create_pipeline(wgpu::TextureFormat::Rgba8Unorm);
+        let pipeline_16 =
+            can_render_16bit(&context).then(|| create_pipeline(wgpu::TextureFormat::Rgba16Unorm));
+        let pipeline_16f = create_pipeline(wgpu::TextureFormat::Rgba16Float);
 
         let sampler = context.device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
@@ -255,53 +586,166 @@ impl YuvToRgbaConverter {
             ..Default::default()
         });
 
+        let compute = ComputePipelines::new(&context, &bind_group_layout);
+
         Self {
             context,
             pipeline,
+            pipeline_16,
+            pipeline_16f,
             vertex_buffer,
             index_buffer,
             num_indices,
             bind_group_layout,
             sampler,
+            planes: None,
+            compute,
+            method: ConversionMethod::Auto,
         }
     }
 
-    pub fn convert(&self, src: &ffmpeg::frame::Video, dst: &Texture) {
-        assert!(Self::is_supported_format(src.format()));
-        src.color_space();
-        let mut textures = Vec::new();
-        for i in 0..3 {
-            let texture = Texture::new_src(
-                &self.context.device,
-                wgpu::TextureFormat::R8Unorm,
-                src.plane_width(i),
-                src.plane_height(i),
-            )
-            .unwrap();
-            self.context.write_texture(
-                &texture.texture,
-                src.data(i),
-                src.stride(i) as u32,
-                src.plane_width(i),
-                src.plane_height(i),
-            );
-            textures.push(texture);
+    pub fn set_method(&mut self, method: ConversionMethod) {
+        self.method = method;
+    }
+
+    /// Usage the output textures need with the current method.
+    pub fn output_usage(&self) -> wgpu::TextureUsages {
+        match (self.method, &self.compute) {
+            (ConversionMethod::Compute, Some(_)) => wgpu::TextureUsages::STORAGE_BINDING,
+            _ => wgpu::TextureUsages::RENDER_ATTACHMENT,
         }
+    }
+
+    /// Converts the visible area of `src` into `dst`, resampling it if `dst`
+    /// is of another size.
+    /// The planes of `src` are uploaded through `uploader`.
+    pub fn convert(
+        &mut self,
+        uploader: &mut Uploader,
+        src: &ffmpeg::frame::Video,
+        dst: &Texture,
+    ) -> anyhow::Result<()> {
+        let layout = FormatLayout::of(src.format())
+            .ok_or_else(|| anyhow::anyhow!("Unsupported format: {:?}", src.format()))?;
+        let key = (src.format(), src.width(), src.height());
+        let planes = match self.planes.take() {
+            Some(planes) if planes.key == key => planes,
+            _ => self.create_planes(key, &layout, src)?,
+        };
+
+        let mut encoder = self
+            .context
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let sources: Vec<Plane> = planes
+            .textures
+            .iter()
+            .enumerate()
+            .map(|(i, texture)| Plane {
+                texture: &texture.texture,
+                data: src.data(i),
+                pitch: src.stride(i),
+            })
+            .collect();
+        if let Err(e) = uploader.upload(&mut encoder, &sources) {
+            // Keeps the planes for the next frame.
+            self.planes = Some(planes);
+            return Err(e);
+        }
+
         let matrix = matrix_index(src.format(), src.color_space(), src.color_range());
-        let matrix_uniform =
-            self.context
-                .device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        let mut params = layout.params(&matrix);
+        // Only the visible area of the frame is converted.
+        let (x, y, width, height) = crate::ffmpeg::visible_rect(src);
+        let (src_width, src_height) = (src.width() as f32, src.height() as f32);
+        params.crop_offset = [x as f32 / src_width, y as f32 / src_height];
+        params.crop_scale = [width as f32 / src_width, height as f32 / src_height];
+        self.context
+            .queue
+            .write_buffer(&planes.params, 0, bytemuck::bytes_of(&params));
+        let bind_group = &self.planes.insert(planes).bind_group;
+
+        let usage = dst.texture.usage();
+        let use_compute = match self.method {
+            ConversionMethod::Auto => !usage.contains(wgpu::TextureUsages::RENDER_ATTACHMENT),
+            ConversionMethod::RenderPass => false,
+            ConversionMethod::Compute => usage.contains(wgpu::TextureUsages::STORAGE_BINDING),
+        };
+        match self.compute.as_mut() {
+            Some(compute) if use_compute => {
+                compute.dispatch(&self.context, &mut encoder, bind_group, dst);
+            }
+            _ => {
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                     label: None,
-                    contents: bytemuck::cast_slice(&[matrix.index()]),
-                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &dst.view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color {
+                                r: 0.0,
+                                g: 0.0,
+                                b: 0.0,
+                                a: 1.0,
+                            }),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
                 });
 
+                let pipeline = match (dst.texture.format(), &self.pipeline_16) {
+                    (wgpu::TextureFormat::Rgba16Unorm, Some(pipeline)) => pipeline,
+                    (wgpu::TextureFormat::Rgba16Float, _) => &self.pipeline_16f,
+                    _ => &self.pipeline,
+                };
+                render_pass.set_pipeline(pipeline);
+                render_pass.set_bind_group(0, bind_group, &[]);
+                render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+                render_pass
+                    .set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+                render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+            }
+        }
+        self.context.queue.submit(Some(encoder.finish()));
+        uploader.recall();
+        Ok(())
+    }
+
+    /// Creates the plane textures, uniform buffer and bind group for frames
+    /// with the format and size of `src`.
+    fn create_planes(
+        &self,
+        key: (ffmpeg::format::Pixel, u32, u32),
+        layout: &FormatLayout,
+        src: &ffmpeg::frame::Video,
+    ) -> anyhow::Result<Planes> {
+        let textures = (0..layout.plane_count())
+            .map(|i| {
+                Texture::new_src(
+                    &self.context.device,
+                    layout.texture_format(i),
+                    src.plane_width(i),
+                    src.plane_height(i),
+                )
+            })
+            .collect::<anyhow::Result<Vec<Texture>>>()?;
+        let params = self.context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: std::mem::size_of::<Params>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // With interleaved chroma, the V plane is bound to the UV plane too.
+        let views = [0, 1, textures.len() - 1].map(|i| &textures[i].view);
         let mut entries = Vec::new();
-        for i in 0..3 {
+        for (i, view) in views.into_iter().enumerate() {
             entries.push(wgpu::BindGroupEntry {
                 binding: i as u32,
-                resource: wgpu::BindingResource::TextureView(&textures[i].view),
+                resource: wgpu::BindingResource::TextureView(view),
             });
         }
         entries.push(wgpu::BindGroupEntry {
@@ -311,9 +755,9 @@ impl YuvToRgbaConverter {
         entries.push(wgpu::BindGroupEntry {
             binding: 4,
             resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                buffer: &matrix_uniform,
+                buffer: &params,
                 offset: 0,
-                size: Some(std::num::NonZeroU64::new(std::mem::size_of::<u32>() as u64).unwrap()),
+                size: None,
             }),
         });
 
@@ -326,55 +770,49 @@ impl YuvToRgbaConverter {
                 label: Some("source_texture_bind_group"),
             });
 
-        let mut encoder = self
-            .context
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &dst.view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.0,
-                            g: 0.0,
-                            b: 0.0,
-                            a: 1.0,
-                        }),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
+        Ok(Planes {
+            key,
+            textures,
+            params,
+            bind_group,
+        })
+    }
 
-            render_pass.set_pipeline(&self.pipeline);
-            render_pass.set_bind_group(0, &bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+    /// Format of the texture to convert frames in `format` to with the
+    /// current method. High bit depth frames go to `Rgba16Unorm`, which
+    /// keeps their precision, when the device can write it with the method.
+    /// Otherwise they go to `Rgba16Float`, whose 11-bit significand keeps
+    /// 10-bit samples exact but rounds off the lowest bit of 12-bit samples
+    /// near white, and up to 5 bits of 16-bit ones.
+    pub fn output_format(&self, format: ffmpeg::format::Pixel) -> wgpu::TextureFormat {
+        match FormatLayout::of(format) {
+            Some(layout) if layout.is_16bit() => {
+                let unorm = match self.output_usage() {
+                    wgpu::TextureUsages::STORAGE_BINDING => self
+                        .compute
+                        .as_ref()
+                        .is_some_and(|compute| compute.pipeline_16.is_some()),
+                    _ => self.pipeline_16.is_some(),
+                };
+                if unorm {
+                    wgpu::TextureFormat::Rgba16Unorm
+                } else {
+                    wgpu::TextureFormat::Rgba16Float
+                }
+            }
+            _ => wgpu::TextureFormat::Rgba8Unorm,
         }
-        self.context.queue.submit(Some(encoder.finish()));
     }
 
-    pub fn is_supported_format(format: ffmpeg::format::Pixel) -> bool {
-        match format {
-            ffmpeg::format::Pixel::YUV410P
-            | ffmpeg::format::Pixel::YUV411P
-            | ffmpeg::format::Pixel::YUVJ411P
-            | ffmpeg::format::Pixel::YUV420P
-            | ffmpeg::format::Pixel::YUVJ420P
-            | ffmpeg::format::Pixel::YUV422P
-            | ffmpeg::format::Pixel::YUVJ422P
-            | ffmpeg::format::Pixel::YUV440P
-            | ffmpeg::format::Pixel::YUVJ440P
-            | ffmpeg::format::Pixel::YUV444P
-            | ffmpeg::format::Pixel::YUVJ444P => true,
-            _ => false,
+    /// Whether frames in `format` can be converted on a device with
+    /// `features`. High bit depth formats need 16-bit normalized textures.
+    pub fn is_supported_format(format: ffmpeg::format::Pixel, features: wgpu::Features) -> bool {
+        match FormatLayout::of(format) {
+            Some(layout) if layout.is_16bit() => {
+                features.contains(wgpu::Features::TEXTURE_FORMAT_16BIT_NORM)
+            }
+            Some(_) => true,
+            None => false,
         }
     }
 }
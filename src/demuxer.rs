@@ -1,31 +1,113 @@
 use crate::*;
-use std::sync::{Arc, Mutex};
+use ffmpeg_next::packet::{Mut, Packet};
+use ffmpeg_next::Rescale;
+use std::{
+    cell::Cell,
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant},
+};
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum SeekMode {
+    /// Seeks to the keyframe at or before the position. Fast, but decoding
+    /// resumes somewhat before the requested position.
+    Keyframe,
+    /// Seeks to the keyframe at or before the position, then the decoders
+    /// discard the frames before the position.
+    Accurate,
+}
+
+/// Maximum number of packets buffered for a stream that is not the one
+/// currently being read. The demuxer blocks once a queue is full, until the
+/// consumer of that stream catches up.
+const MAX_QUEUED_PACKETS: usize = 128;
+
+/// Delay before reading again a source that had no data available.
+const RETRY_INTERVAL: Duration = Duration::from_millis(10);
 
 pub struct DemuxerStream {
-    inner: Arc<Mutex<DemuxerStreamInner>>,
+    shared: Arc<DemuxerShared>,
+    index: usize,
+    // Serial of the last seek seen by this stream.
+    serial: Cell<u64>,
 }
 
 impl DemuxerStream {
-    fn new(inner: Arc<Mutex<DemuxerStreamInner>>) -> Self {
-        DemuxerStream { inner }
+    fn new(shared: Arc<DemuxerShared>, index: usize) -> Self {
+        let serial = {
+            let mut state = shared.lock();
+            state.streams[index].consumers += 1;
+            state.serial
+        };
+        DemuxerStream {
+            shared,
+            index,
+            serial: Cell::new(serial),
+        }
     }
 
     pub fn index(&self) -> usize {
-        self.inner.lock().unwrap().index()
+        self.index
     }
 
     pub fn media_type(&self) -> MediaType {
-        self.inner.lock().unwrap().media_type()
+        self.shared.lock().streams[self.index].media_type()
+    }
+
+    pub fn time_base(&self) -> ffmpeg_next::Rational {
+        unsafe { (*self.as_ptr()).time_base.into() }
+    }
+
+    pub(crate) unsafe fn as_ptr(&self) -> *const AVStream {
+        self.shared.lock().streams[self.index].context.as_ptr()
+    }
+
+    /// Returns the next packet of this stream, or `None` at end of input.
+    ///
+    /// Packets of other streams read along the way are queued for their own
+    /// `DemuxerStream`. Streams that are read on the same `Demuxer` should be
+    /// consumed concurrently, otherwise the reader blocks on a full queue.
+    pub fn read(&self) -> Result<Option<Packet>, MediaError> {
+        Ok(self.read_packet()?.0)
+    }
+
+    /// Like `read`, but also tells whether the demuxer has been seeked since
+    /// the previous packet, in which case the decoder has to be flushed first.
+    pub(crate) fn read_packet(&self) -> Result<(Option<Packet>, bool), MediaError> {
+        let (packet, serial) = self.shared.read(self.index)?;
+        Ok((packet, self.serial.replace(serial) != serial))
+    }
+
+    /// Returns `true` once after each seek of the demuxer.
+    pub(crate) fn take_discontinuity(&self) -> bool {
+        let serial = self.shared.lock().serial;
+        self.serial.replace(serial) != serial
     }
 
-    pub fn read(&self) -> Option<ffmpeg_next::packet::Packet> {
-        unimplemented!()
+    /// Position of the last accurate seek in the time base of this stream.
+    /// Decoders discard the frames before it.
+    pub(crate) fn seek_target(&self) -> Option<i64> {
+        let target = self.shared.lock().seek_target?;
+        Some(target.rescale(ffmpeg_next::rescale::TIME_BASE, self.time_base()))
+    }
+}
+
+impl Drop for DemuxerStream {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        let stream = &mut state.streams[self.index];
+        stream.consumers -= 1;
+        if stream.consumers == 0 {
+            stream.queue.clear();
+        }
+        self.shared.cond.notify_all();
     }
 }
 
 pub struct Demuxer {
-    _context: Arc<ffmpeg::context::InputFormat>,
-    streams: Vec<Arc<Mutex<DemuxerStreamInner>>>,
+    shared: Arc<DemuxerShared>,
 }
 
 impl Demuxer {
@@ -33,36 +115,220 @@ impl Demuxer {
         let context = Arc::new(source.into_context());
         let nb_streams = unsafe { (*context.as_ptr()).nb_streams as usize };
         let streams = (0..nb_streams)
-            .map(|i| Arc::new(Mutex::new(DemuxerStreamInner::new(context.clone(), i))))
+            .map(|i| DemuxerStreamInner::new(context.clone(), i))
             .collect();
         Demuxer {
-            _context: context,
-            streams,
+            shared: Arc::new(DemuxerShared {
+                context,
+                state: Mutex::new(DemuxerState {
+                    streams,
+                    reading: false,
+                    eof: false,
+                    seek_pending: 0,
+                    serial: 0,
+                    seek_target: None,
+                }),
+                cond: Condvar::new(),
+            }),
         }
     }
 
     pub fn stream(&self, index: usize) -> Option<DemuxerStream> {
-        self.streams
-            .get(index)
-            .map(|s| DemuxerStream::new(s.clone()))
+        if index < self.shared.lock().streams.len() {
+            Some(DemuxerStream::new(self.shared.clone(), index))
+        } else {
+            None
+        }
+    }
+
+    /// Seeks all the streams to `position` and discards the queued packets.
+    /// Decoders reading from the streams are flushed on their next read.
+    ///
+    /// `position` is relative to the start time of the input, like ffplay,
+    /// so that inputs not starting at 0, e.g. MPEG-TS, seek to the same
+    /// place as others.
+    pub fn seek(&self, position: Duration, mode: SeekMode) -> Result<(), MediaError> {
+        let mut timestamp = position.as_micros().min(i64::MAX as u128) as i64;
+        let start_time = unsafe { (*self.shared.context.as_ptr()).start_time };
+        if start_time != AV_NOPTS_VALUE {
+            timestamp = timestamp.saturating_add(start_time);
+        }
+        let target = match mode {
+            SeekMode::Keyframe => None,
+            SeekMode::Accurate => Some(timestamp),
+        };
+        self.shared.seek(timestamp, target)
+    }
+
+    /// Aborts the pending and all the following reads and seeks, which fail
+    /// with `MediaError::Cancelled`, e.g. to tear down a stuck network
+    /// stream.
+    pub fn cancel(&self) {
+        self.shared.context.interrupt().abort();
+    }
+}
+
+struct DemuxerShared {
+    context: Arc<ffmpeg::context::InputFormat>,
+    state: Mutex<DemuxerState>,
+    cond: Condvar,
+}
+
+struct DemuxerState {
+    streams: Vec<DemuxerStreamInner>,
+    // Set while one consumer is inside `av_read_frame`, which is called
+    // without holding the lock.
+    reading: bool,
+    eof: bool,
+    // Number of seeks waiting for the current read to complete.
+    seek_pending: usize,
+    // Incremented by every seek.
+    serial: u64,
+    // Position of the last seek in `AV_TIME_BASE`, if it is accurate.
+    seek_target: Option<i64>,
+}
+
+impl DemuxerShared {
+    fn lock(&self) -> MutexGuard<'_, DemuxerState> {
+        self.state.lock().unwrap()
+    }
+
+    fn read(&self, index: usize) -> Result<(Option<Packet>, u64), MediaError> {
+        let mut state = self.lock();
+        loop {
+            if let Some(packet) = state.streams[index].queue.pop_front() {
+                self.cond.notify_all();
+                return Ok((Some(packet), state.serial));
+            }
+            if state.eof {
+                return Ok((None, state.serial));
+            }
+            if state.reading || state.seek_pending > 0 {
+                state = self.cond.wait(state).unwrap();
+                continue;
+            }
+
+            state.reading = true;
+            drop(state);
+            let packet = self.read_frame();
+            state = self.lock();
+
+            match packet {
+                Ok(Some(packet)) if packet.stream() == index => {
+                    state.reading = false;
+                    self.cond.notify_all();
+                    return Ok((Some(packet), state.serial));
+                }
+                Ok(Some(packet)) => {
+                    let stream = packet.stream();
+                    while state.seek_pending == 0
+                        && state
+                            .streams
+                            .get(stream)
+                            .is_some_and(|s| s.consumers > 0 && s.queue.len() >= MAX_QUEUED_PACKETS)
+                    {
+                        state = self.cond.wait(state).unwrap();
+                    }
+                    // Packets read before a seek are stale.
+                    let seek_pending = state.seek_pending > 0;
+                    if let Some(s) = state.streams.get_mut(stream) {
+                        if s.consumers > 0 && !seek_pending {
+                            s.queue.push_back(packet);
+                        }
+                    }
+                }
+                Ok(None) => state.eof = true,
+                Err(e) => {
+                    state.reading = false;
+                    self.cond.notify_all();
+                    return Err(e);
+                }
+            }
+            state.reading = false;
+            self.cond.notify_all();
+        }
+    }
+
+    fn seek(&self, timestamp: i64, target: Option<i64>) -> Result<(), MediaError> {
+        let mut state = self.lock();
+        state.seek_pending += 1;
+        self.cond.notify_all();
+        while state.reading {
+            state = self.cond.wait(state).unwrap();
+        }
+
+        state.reading = true;
+        drop(state);
+        let result = self.context.interrupt().run(|| unsafe {
+            avformat_seek_file(
+                self.context.as_mut_ptr(),
+                -1,
+                i64::MIN,
+                timestamp,
+                timestamp,
+                0,
+            )
+        });
+        state = self.lock();
+
+        state.reading = false;
+        state.seek_pending -= 1;
+        if result.is_ok() {
+            state.streams.iter_mut().for_each(|s| s.queue.clear());
+            state.eof = false;
+            state.serial += 1;
+            state.seek_target = target;
+        }
+        self.cond.notify_all();
+        result.map(|_| ())
+    }
+
+    /// Reads the next packet of any stream, `None` at end of input. Reads
+    /// failing with `EAGAIN` are retried after `RETRY_INTERVAL`, under the
+    /// timeout of the whole operation.
+    fn read_frame(&self) -> Result<Option<Packet>, MediaError> {
+        let mut packet = Packet::empty();
+        let interrupt = self.context.interrupt();
+        let deadline = interrupt.deadline();
+        loop {
+            match interrupt.run_until(deadline, || unsafe {
+                av_read_frame(self.context.as_mut_ptr(), packet.as_mut_ptr())
+            }) {
+                Ok(_) => return Ok(Some(packet)),
+                Err(MediaError::Other(ffmpeg_next::Error::Eof)) => return Ok(None),
+                Err(MediaError::Other(ffmpeg_next::Error::Other {
+                    errno: ffmpeg_next::error::EAGAIN,
+                })) => {
+                    // Cancellations and the deadline are checked by the next
+                    // `run_until`.
+                    let delay = deadline.map_or(RETRY_INTERVAL, |deadline| {
+                        deadline
+                            .saturating_duration_since(Instant::now())
+                            .min(RETRY_INTERVAL)
+                    });
+                    thread::sleep(delay);
+                }
+                Err(e) => return Err(e),
+            }
+        }
     }
 }
 
 struct DemuxerStreamInner {
     context: ffmpeg::context::InputStream,
+    queue: VecDeque<Packet>,
+    consumers: usize,
 }
 
 impl DemuxerStreamInner {
     fn new(context: Arc<ffmpeg::context::InputFormat>, index: usize) -> Self {
         DemuxerStreamInner {
             context: ffmpeg::context::InputStream::wrap(context, index),
+            queue: VecDeque::new(),
+            consumers: 0,
         }
     }
 
-    fn index(&self) -> usize {
-        self.context.index()
-    }
-
     fn media_type(&self) -> MediaType {
         unsafe { ffmpeg::media_type_of_stream(self.context.as_ptr()) }
     }
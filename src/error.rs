@@ -0,0 +1,64 @@
+use ffmpeg_next::error::{EACCES, ENOENT, EPERM, ETIMEDOUT};
+use ffmpeg_next::ffi::*;
+use std::{ffi::c_int, fmt};
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum MediaError {
+    /// The path or URL can not be passed to FFmpeg, e.g. it contains a NUL.
+    InvalidPath,
+    NotFound,
+    InvalidData,
+    PermissionDenied,
+    ProtocolNotFound,
+    UnsupportedFormat,
+    /// The operation was aborted through a `CancellationToken`, or because
+    /// the source is being torn down.
+    Cancelled,
+    TimedOut,
+    Other(ffmpeg_next::Error),
+}
+
+impl From<c_int> for MediaError {
+    fn from(value: c_int) -> Self {
+        match value {
+            e if e == AVERROR(ENOENT) || e == AVERROR_HTTP_NOT_FOUND => MediaError::NotFound,
+            e if e == AVERROR(EACCES)
+                || e == AVERROR(EPERM)
+                || e == AVERROR_HTTP_FORBIDDEN
+                || e == AVERROR_HTTP_UNAUTHORIZED =>
+            {
+                MediaError::PermissionDenied
+            }
+            AVERROR_INVALIDDATA => MediaError::InvalidData,
+            AVERROR_PROTOCOL_NOT_FOUND => MediaError::ProtocolNotFound,
+            AVERROR_DEMUXER_NOT_FOUND | AVERROR_DECODER_NOT_FOUND => MediaError::UnsupportedFormat,
+            AVERROR_EXIT => MediaError::Cancelled,
+            e if e == AVERROR(ETIMEDOUT) => MediaError::TimedOut,
+            e => MediaError::Other(e.into()),
+        }
+    }
+}
+
+impl From<ffmpeg_next::Error> for MediaError {
+    fn from(value: ffmpeg_next::Error) -> Self {
+        c_int::from(value).into()
+    }
+}
+
+impl fmt::Display for MediaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MediaError::InvalidPath => f.write_str("Invalid path"),
+            MediaError::NotFound => f.write_str("Not found"),
+            MediaError::InvalidData => f.write_str("Invalid data found when processing input"),
+            MediaError::PermissionDenied => f.write_str("Permission denied"),
+            MediaError::ProtocolNotFound => f.write_str("Protocol not found"),
+            MediaError::UnsupportedFormat => f.write_str("Unsupported format"),
+            MediaError::Cancelled => f.write_str("Cancelled"),
+            MediaError::TimedOut => f.write_str("Timed out"),
+            MediaError::Other(e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl std::error::Error for MediaError {}
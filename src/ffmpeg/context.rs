@@ -1,13 +1,30 @@
+use super::{interrupt::Interrupt, io::IoContext};
 use ffmpeg_next::ffi::*;
 use std::sync::Arc;
 
 pub(crate) struct InputFormat {
     ptr: *mut AVFormatContext,
+    // Custom I/O of the context, released after it.
+    _io: Option<IoContext>,
+    // Installed as the interrupt callback of the context.
+    interrupt: Box<Interrupt>,
 }
 
 impl InputFormat {
-    pub unsafe fn wrap(ptr: *mut AVFormatContext) -> Self {
-        InputFormat { ptr }
+    pub unsafe fn wrap(
+        ptr: *mut AVFormatContext,
+        io: Option<IoContext>,
+        interrupt: Box<Interrupt>,
+    ) -> Self {
+        InputFormat {
+            ptr,
+            _io: io,
+            interrupt,
+        }
+    }
+
+    pub fn interrupt(&self) -> &Interrupt {
+        &self.interrupt
     }
 
     pub unsafe fn as_ptr(&self) -> *const AVFormatContext {
@@ -19,6 +36,11 @@ impl InputFormat {
     }
 }
 
+// The context is only ever used by one thread at a time: callers serialize
+// access to it (see `Demuxer`).
+unsafe impl Send for InputFormat {}
+unsafe impl Sync for InputFormat {}
+
 impl Drop for InputFormat {
     fn drop(&mut self) {
         unsafe {
@@ -34,7 +56,7 @@ pub(crate) struct InputStream {
 
 impl InputStream {
     pub unsafe fn as_ptr(&self) -> *const AVStream {
-        (*self.context.as_ptr()).streams.add(self.index) as *const _
+        *(*self.context.as_ptr()).streams.add(self.index) as *const _
     }
 }
 
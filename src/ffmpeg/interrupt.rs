@@ -0,0 +1,96 @@
+use crate::{cancellation::CancellationToken, MediaError};
+use ffmpeg_next::ffi::*;
+use std::{
+    ffi::{c_int, c_void},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// State of the interrupt callback of a format context, which FFmpeg polls
+/// while blocked in I/O.
+pub(crate) struct Interrupt {
+    // Given by the user, possibly shared with other sources.
+    token: Option<CancellationToken>,
+    // Owned by the context, e.g. set when its `Demuxer` is torn down.
+    abort: CancellationToken,
+    timeout: Option<Duration>,
+    deadline: Mutex<Option<Instant>>,
+}
+
+impl Interrupt {
+    pub fn new(token: Option<CancellationToken>, timeout: Option<Duration>) -> Box<Self> {
+        Box::new(Interrupt {
+            token,
+            abort: CancellationToken::new(),
+            timeout,
+            deadline: Mutex::new(None),
+        })
+    }
+
+    /// The callback to install on the context. `self` must outlive it.
+    pub fn callback(&self) -> AVIOInterruptCB {
+        AVIOInterruptCB {
+            callback: Some(interrupt_callback),
+            opaque: self as *const Self as *mut c_void,
+        }
+    }
+
+    pub fn abort(&self) {
+        self.abort.cancel();
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.abort.is_cancelled() || self.token.as_ref().is_some_and(|t| t.is_cancelled())
+    }
+
+    fn is_interrupted(&self) -> bool {
+        self.is_cancelled()
+            || self
+                .deadline
+                .lock()
+                .unwrap()
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Deadline of an operation starting now.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.timeout.map(|timeout| Instant::now() + timeout)
+    }
+
+    /// Runs a blocking FFmpeg call under the operation timeout. Negative
+    /// results are returned as errors, telling apart cancellations and
+    /// timeouts.
+    pub fn run(&self, f: impl FnOnce() -> c_int) -> Result<c_int, MediaError> {
+        self.run_until(self.deadline(), f)
+    }
+
+    /// Like `run`, but with the deadline of an operation made of several
+    /// calls, e.g. a read that is retried on `EAGAIN`.
+    pub fn run_until(
+        &self,
+        deadline: Option<Instant>,
+        f: impl FnOnce() -> c_int,
+    ) -> Result<c_int, MediaError> {
+        if self.is_cancelled() {
+            return Err(MediaError::Cancelled);
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(MediaError::TimedOut);
+        }
+        *self.deadline.lock().unwrap() = deadline;
+        let result = f();
+        let timed_out = self.is_interrupted();
+        *self.deadline.lock().unwrap() = None;
+        match result {
+            r if r >= 0 => Ok(r),
+            AVERROR_EXIT if self.is_cancelled() => Err(MediaError::Cancelled),
+            AVERROR_EXIT if timed_out => Err(MediaError::TimedOut),
+            e => Err(e.into()),
+        }
+    }
+}
+
+unsafe extern "C" fn interrupt_callback(opaque: *mut c_void) -> c_int {
+    let interrupt = &*(opaque as *const Interrupt);
+    interrupt.is_interrupted() as c_int
+}
@@ -0,0 +1,131 @@
+use ffmpeg_next::ffi::*;
+use std::{
+    ffi::{c_int, c_void},
+    io::{self, Read, Seek, SeekFrom},
+    slice,
+};
+
+const BUFFER_SIZE: usize = 32 * 1024;
+
+pub(crate) trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+/// An `AVIOContext` reading from a Rust reader.
+pub(crate) struct IoContext {
+    ptr: *mut AVIOContext,
+    reader: *mut Box<dyn ReadSeek>,
+}
+
+impl IoContext {
+    pub fn new(reader: Box<dyn ReadSeek>) -> Option<Self> {
+        unsafe {
+            let buffer = av_malloc(BUFFER_SIZE) as *mut u8;
+            if buffer.is_null() {
+                return None;
+            }
+            let reader = Box::into_raw(Box::new(reader));
+            let ptr = avio_alloc_context(
+                buffer,
+                BUFFER_SIZE as c_int,
+                0,
+                reader as *mut c_void,
+                Some(read_packet),
+                None,
+                Some(seek),
+            );
+            if ptr.is_null() {
+                av_free(buffer as *mut c_void);
+                drop(Box::from_raw(reader));
+                return None;
+            }
+            Some(IoContext { ptr, reader })
+        }
+    }
+
+    pub unsafe fn as_mut_ptr(&self) -> *mut AVIOContext {
+        self.ptr
+    }
+}
+
+// The reader is `Send` and only used through the format context, whose
+// access is serialized.
+unsafe impl Send for IoContext {}
+unsafe impl Sync for IoContext {}
+
+impl Drop for IoContext {
+    fn drop(&mut self) {
+        unsafe {
+            // The buffer may have been reallocated by FFmpeg.
+            av_freep(&mut (*self.ptr).buffer as *mut _ as *mut c_void);
+            avio_context_free(&mut self.ptr);
+            drop(Box::from_raw(self.reader));
+        }
+    }
+}
+
+fn to_error(e: io::Error) -> c_int {
+    AVERROR(e.raw_os_error().unwrap_or(EIO))
+}
+
+unsafe extern "C" fn read_packet(opaque: *mut c_void, buf: *mut u8, buf_size: c_int) -> c_int {
+    let reader = &mut *(opaque as *mut Box<dyn ReadSeek>);
+    let buf = slice::from_raw_parts_mut(buf, buf_size as usize);
+    loop {
+        match reader.read(buf) {
+            Ok(0) => return AVERROR_EOF,
+            Ok(n) => return n as c_int,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return to_error(e),
+        }
+    }
+}
+
+unsafe extern "C" fn seek(opaque: *mut c_void, offset: i64, whence: c_int) -> i64 {
+    let reader = &mut *(opaque as *mut Box<dyn ReadSeek>);
+    if whence & AVSEEK_SIZE != 0 {
+        return match stream_len(reader.as_mut()) {
+            Ok(len) => len as i64,
+            Err(e) => to_error(e) as i64,
+        };
+    }
+    let position = match whence & !AVSEEK_FORCE {
+        0 => match u64::try_from(offset) {
+            Ok(offset) => SeekFrom::Start(offset),
+            Err(_) => return AVERROR(EINVAL) as i64,
+        },
+        1 => SeekFrom::Current(offset),
+        2 => SeekFrom::End(offset),
+        _ => return AVERROR(EINVAL) as i64,
+    };
+    match reader.seek(position) {
+        Ok(position) => position as i64,
+        Err(e) => to_error(e) as i64,
+    }
+}
+
+fn stream_len(reader: &mut dyn ReadSeek) -> io::Result<u64> {
+    let position = reader.stream_position()?;
+    let len = reader.seek(SeekFrom::End(0))?;
+    if position != len {
+        reader.seek(SeekFrom::Start(position))?;
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seek_rejects_negative_offsets() {
+        let mut reader: Box<dyn ReadSeek> = Box::new(io::Cursor::new(vec![0u8; 16]));
+        let opaque = &mut reader as *mut Box<dyn ReadSeek> as *mut c_void;
+        unsafe {
+            assert_eq!(seek(opaque, -1, 0), AVERROR(EINVAL) as i64);
+            assert_eq!(seek(opaque, 8, 0), 8);
+            assert_eq!(seek(opaque, -4, 1), 4);
+            assert_eq!(seek(opaque, 0, AVSEEK_SIZE), 16);
+        }
+    }
+}
@@ -1,7 +1,10 @@
-use crate::NONE_CODEC_PARAMETERS;
-use ffmpeg_next::ffi::AVStream;
+use crate::{demuxer::DemuxerStream, MediaError, NONE_CODEC_PARAMETERS};
+use ffmpeg_next::ffi::*;
+use std::ptr;
 
 pub mod context;
+pub mod interrupt;
+pub mod io;
 
 pub unsafe fn media_type_of_stream(stream: *const AVStream) -> crate::MediaType {
     (*stream)
@@ -10,3 +13,116 @@ pub unsafe fn media_type_of_stream(stream: *const AVStream) -> crate::MediaType
         .map(|codecpar| codecpar.codec_type.into())
         .unwrap_or(crate::MediaType::Unknown(NONE_CODEC_PARAMETERS))
 }
+
+/// Visible area of `frame` as `(x, y, width, height)`, i.e. without its
+/// crop borders, which decoders leave in place when opened without
+/// `apply_cropping`. The whole frame if the borders are invalid.
+pub fn visible_rect(frame: &ffmpeg_next::frame::Video) -> (u32, u32, u32, u32) {
+    let (width, height) = (frame.width() as usize, frame.height() as usize);
+    let (left, right, top, bottom) = unsafe {
+        let frame = &*frame.as_ptr();
+        (
+            frame.crop_left,
+            frame.crop_right,
+            frame.crop_top,
+            frame.crop_bottom,
+        )
+    };
+    if left.saturating_add(right) >= width || top.saturating_add(bottom) >= height {
+        return (0, 0, width as u32, height as u32);
+    }
+    (
+        left as u32,
+        top as u32,
+        (width - left - right) as u32,
+        (height - top - bottom) as u32,
+    )
+}
+
+/// Creates a codec context, not opened yet, from the parameters of `stream`.
+pub unsafe fn new_codec_context(
+    stream: *const AVStream,
+) -> Result<ffmpeg_next::codec::Context, MediaError> {
+    let context = avcodec_alloc_context3(ptr::null());
+    if context.is_null() {
+        return Err(AVERROR(ENOMEM).into());
+    }
+    let mut context = ffmpeg_next::codec::Context::wrap(context, None);
+    match avcodec_parameters_to_context(context.as_mut_ptr(), (*stream).codecpar) {
+        e if e < 0 => Err(e.into()),
+        _ => Ok(context),
+    }
+}
+
+/// Decoding state shared by the decoders built on a `DemuxerStream`.
+#[derive(Default)]
+pub struct DecodeState {
+    /// Set once the end of the stream has been sent to the decoder.
+    pub draining: bool,
+    /// Frames before this timestamp are discarded after an accurate seek.
+    pub skip_until: Option<i64>,
+    /// Set whenever the decoder is flushed because of a seek.
+    pub flushed: bool,
+}
+
+impl DecodeState {
+    fn flush(&mut self, decoder: &mut ffmpeg_next::decoder::Opened, stream: &DemuxerStream) {
+        decoder.flush();
+        self.draining = false;
+        self.skip_until = stream.seek_target();
+        self.flushed = true;
+    }
+}
+
+/// Receives the next frame from `decoder`, feeding it with the packets of
+/// `stream` as needed. Returns `false` once the decoder has been drained. The
+/// decoder is flushed whenever the demuxer has been seeked.
+pub fn receive_frame(
+    decoder: &mut ffmpeg_next::decoder::Opened,
+    stream: &DemuxerStream,
+    frame: &mut ffmpeg_next::Frame,
+    state: &mut DecodeState,
+) -> Result<bool, MediaError> {
+    if stream.take_discontinuity() {
+        state.flush(decoder, stream);
+    }
+
+    loop {
+        match decoder.receive_frame(frame) {
+            Ok(()) => match (state.skip_until, frame.timestamp()) {
+                (Some(target), Some(timestamp)) if timestamp < target => continue,
+                _ => {
+                    state.skip_until = None;
+                    return Ok(true);
+                }
+            },
+            Err(ffmpeg_next::Error::Eof) => return Ok(false),
+            Err(ffmpeg_next::Error::Other {
+                errno: ffmpeg_next::error::EAGAIN,
+            }) => (),
+            Err(e) => return Err(e.into()),
+        }
+
+        if state.draining {
+            return Ok(false);
+        }
+
+        let (packet, discontinuity) = stream.read_packet()?;
+        if discontinuity {
+            state.flush(decoder, stream);
+        }
+        match packet {
+            Some(packet) => match decoder.send_packet(&packet) {
+                Ok(()) => (),
+                Err(ffmpeg_next::Error::InvalidData) => {
+                    log::warn!("Skip invalid packet of stream {}", packet.stream());
+                }
+                Err(e) => return Err(e.into()),
+            },
+            None => {
+                decoder.send_eof()?;
+                state.draining = true;
+            }
+        }
+    }
+}
@@ -1,16 +1,25 @@
 use ffmpeg_next::ffi::AVMediaType::*;
 use ffmpeg_next::ffi::*;
 
+pub mod audio_decoder;
+pub mod audio_sink;
+pub mod cancellation;
+pub mod clock;
 pub mod demuxer;
+pub mod error;
 pub mod media_source;
+pub mod player;
+pub mod video_decoder;
 pub mod video_renderer;
+pub mod wgpu_context;
+pub use self::error::*;
 pub use self::media_source::*;
 
 mod convert_from_yuv;
 mod ffmpeg;
 mod presenter;
 mod texture;
-mod wgpu_context;
+mod uploader;
 
 #[derive(Eq, PartialEq, Debug, Clone, Copy)]
 pub enum MediaType {
@@ -1,9 +1,13 @@
+use crate::cancellation::CancellationToken;
+use crate::ffmpeg::{interrupt::Interrupt, io::IoContext};
 use crate::*;
 use std::{
-    ffi::{c_int, CStr, CString},
+    ffi::{c_char, c_int, CStr, CString},
+    io::{self, Read, Seek},
     path::Path,
     ptr,
     str::from_utf8_unchecked,
+    time::Duration,
 };
 
 pub struct Format {
@@ -22,16 +26,333 @@ impl Format {
             unsafe { from_utf8_unchecked(CStr::from_ptr((*self.ptr).name).to_bytes()) }
         }
     }
+
+    /// Descriptive name of the format, e.g. "QuickTime / MOV".
+    pub fn long_name(&self) -> &str {
+        unsafe { self.field(|f| f.long_name) }
+    }
+
+    /// File extensions commonly used for the format.
+    pub fn extensions(&self) -> Vec<&str> {
+        unsafe { self.field(|f| f.extensions) }
+            .split(',')
+            .filter(|s| !s.is_empty())
+            .collect()
+    }
+
+    /// Comma separated MIME types of the format, may be empty.
+    pub fn mime_type(&self) -> &str {
+        unsafe { self.field(|f| f.mime_type) }
+    }
+
+    unsafe fn field(&self, f: impl FnOnce(&AVInputFormat) -> *const c_char) -> &str {
+        match self.ptr.as_ref().map(f) {
+            Some(ptr) if !ptr.is_null() => from_utf8_unchecked(CStr::from_ptr(ptr).to_bytes()),
+            _ => "",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Chapter {
+    id: i64,
+    start: Duration,
+    end: Duration,
+    title: Option<String>,
+}
+
+impl Chapter {
+    unsafe fn from_chapter(chapter: *const AVChapter) -> Self {
+        let time_base = (*chapter).time_base.into();
+        Chapter {
+            id: (*chapter).id as i64,
+            start: clock::pts_to_duration((*chapter).start, time_base),
+            end: clock::pts_to_duration((*chapter).end, time_base),
+            title: dict_get((*chapter).metadata, c"title"),
+        }
+    }
+
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn start(&self) -> Duration {
+        self.start
+    }
+
+    pub fn end(&self) -> Duration {
+        self.end
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+}
+
+/// How the frames of a video are to be rotated and flipped for display, e.g.
+/// for portrait phone footage. The flips apply before the rotation.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub struct Orientation {
+    rotation: u32,
+    flip_horizontal: bool,
+    flip_vertical: bool,
+}
+
+impl Orientation {
+    /// `rotation` is clockwise in degrees, rounded to a quarter turn.
+    pub fn new(rotation: i32, flip_horizontal: bool, flip_vertical: bool) -> Self {
+        let quarter_turns = ((rotation as f64 / 90.0).round() as i32).rem_euclid(4);
+        Orientation {
+            rotation: quarter_turns as u32 * 90,
+            flip_horizontal,
+            flip_vertical,
+        }
+    }
+
+    /// Reads a display matrix the way the ffmpeg tool does to autorotate.
+    unsafe fn from_display_matrix(matrix: &[i32; 9]) -> Self {
+        let theta = -av_display_rotation_get(matrix.as_ptr()).round();
+        if theta.is_nan() {
+            return Orientation::default();
+        }
+        let theta = theta - 360.0 * (theta / 360.0 + 0.9 / 360.0).floor();
+        if (theta - 90.0).abs() < 1.0 {
+            Orientation::new(90, false, matrix[3] > 0)
+        } else if (theta - 180.0).abs() < 1.0 {
+            Orientation::new(0, matrix[0] < 0, matrix[4] < 0)
+        } else if (theta - 270.0).abs() < 1.0 {
+            Orientation::new(270, false, matrix[3] < 0)
+        } else if theta.abs() < 1.0 {
+            Orientation::new(0, false, matrix[4] < 0)
+        } else {
+            log::warn!("Unsupported display rotation of {} degrees", theta);
+            Orientation::default()
+        }
+    }
+
+    /// Clockwise rotation in degrees, 0, 90, 180 or 270.
+    pub fn rotation(&self) -> u32 {
+        self.rotation
+    }
+
+    pub fn flip_horizontal(&self) -> bool {
+        self.flip_horizontal
+    }
+
+    pub fn flip_vertical(&self) -> bool {
+        self.flip_vertical
+    }
+
+    /// Whether the width and height of the frames are swapped for display.
+    pub fn is_transposed(&self) -> bool {
+        self.rotation % 180 != 0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VideoInfo {
+    width: u32,
+    height: u32,
+    pixel_format: ffmpeg_next::format::Pixel,
+    sample_aspect_ratio: Option<ffmpeg_next::Rational>,
+    avg_frame_rate: Option<ffmpeg_next::Rational>,
+    real_frame_rate: Option<ffmpeg_next::Rational>,
+    color_space: ffmpeg_next::color::Space,
+    color_range: ffmpeg_next::color::Range,
+    color_primaries: ffmpeg_next::color::Primaries,
+    color_transfer: ffmpeg_next::color::TransferCharacteristic,
+    orientation: Orientation,
+}
+
+impl VideoInfo {
+    unsafe fn from_stream(stream: *const AVStream) -> Self {
+        let codecpar = &*(*stream).codecpar;
+        let sample_aspect_ratio = if (*stream).sample_aspect_ratio.num != 0 {
+            (*stream).sample_aspect_ratio
+        } else {
+            codecpar.sample_aspect_ratio
+        };
+        VideoInfo {
+            width: codecpar.width as u32,
+            height: codecpar.height as u32,
+            pixel_format: pixel_format(codecpar.format),
+            sample_aspect_ratio: to_rational(sample_aspect_ratio),
+            avg_frame_rate: to_rational((*stream).avg_frame_rate),
+            real_frame_rate: to_rational((*stream).r_frame_rate),
+            color_space: codecpar.color_space.into(),
+            color_range: codecpar.color_range.into(),
+            color_primaries: codecpar.color_primaries.into(),
+            color_transfer: codecpar.color_trc.into(),
+            orientation: display_matrix(codecpar)
+                .map(|matrix| Orientation::from_display_matrix(&matrix))
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixel_format(&self) -> ffmpeg_next::format::Pixel {
+        self.pixel_format
+    }
+
+    /// Sample (pixel) aspect ratio, `None` if unknown.
+    pub fn sample_aspect_ratio(&self) -> Option<ffmpeg_next::Rational> {
+        self.sample_aspect_ratio
+    }
+
+    /// Display aspect ratio, assuming square pixels if the sample aspect
+    /// ratio is unknown.
+    pub fn display_aspect_ratio(&self) -> Option<ffmpeg_next::Rational> {
+        if self.width == 0 || self.height == 0 {
+            return None;
+        }
+        let sar = self
+            .sample_aspect_ratio
+            .unwrap_or(ffmpeg_next::Rational::new(1, 1));
+        let mut dar = AVRational { num: 0, den: 0 };
+        unsafe {
+            av_reduce(
+                &mut dar.num,
+                &mut dar.den,
+                self.width as i64 * sar.numerator() as i64,
+                self.height as i64 * sar.denominator() as i64,
+                i32::MAX as i64,
+            );
+        }
+        to_rational(dar)
+    }
+
+    pub fn avg_frame_rate(&self) -> Option<ffmpeg_next::Rational> {
+        self.avg_frame_rate
+    }
+
+    /// The lowest frame rate with which all timestamps can be represented
+    /// accurately.
+    pub fn real_frame_rate(&self) -> Option<ffmpeg_next::Rational> {
+        self.real_frame_rate
+    }
+
+    pub fn color_space(&self) -> ffmpeg_next::color::Space {
+        self.color_space
+    }
+
+    pub fn color_range(&self) -> ffmpeg_next::color::Range {
+        self.color_range
+    }
+
+    pub fn color_primaries(&self) -> ffmpeg_next::color::Primaries {
+        self.color_primaries
+    }
+
+    pub fn color_transfer(&self) -> ffmpeg_next::color::TransferCharacteristic {
+        self.color_transfer
+    }
+
+    /// Orientation from the display matrix of the stream, if any.
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AudioInfo {
+    sample_rate: u32,
+    channels: u32,
+    channel_layout: String,
+    sample_format: ffmpeg_next::format::Sample,
+}
+
+impl AudioInfo {
+    unsafe fn from_stream(stream: *const AVStream) -> Self {
+        let codecpar = &*(*stream).codecpar;
+        let mut layout = [0 as c_char; 64];
+        let channel_layout = match av_channel_layout_describe(
+            &codecpar.ch_layout,
+            layout.as_mut_ptr(),
+            layout.len(),
+        ) {
+            r if r > 0 => c_str(layout.as_ptr()).unwrap_or_default(),
+            _ => String::new(),
+        };
+        AudioInfo {
+            sample_rate: codecpar.sample_rate as u32,
+            channels: codecpar.ch_layout.nb_channels as u32,
+            channel_layout,
+            sample_format: sample_format(codecpar.format),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> u32 {
+        self.channels
+    }
+
+    /// Description of the channel layout, e.g. "stereo" or "5.1(side)".
+    pub fn channel_layout(&self) -> &str {
+        &self.channel_layout
+    }
+
+    pub fn sample_format(&self) -> ffmpeg_next::format::Sample {
+        self.sample_format
+    }
 }
 
+#[derive(Debug, Clone)]
 pub struct StreamInfo {
     index: usize,
     media_type: MediaType,
+    codec_id: ffmpeg_next::codec::Id,
+    codec_name: String,
+    profile: Option<String>,
+    bit_rate: Option<u64>,
+    time_base: ffmpeg_next::Rational,
+    duration: Option<Duration>,
+    frame_count: Option<u64>,
+    language: Option<String>,
+    video: Option<VideoInfo>,
+    audio: Option<AudioInfo>,
 }
 
 impl StreamInfo {
-    pub(crate) fn new(index: usize, media_type: MediaType) -> Self {
-        StreamInfo { index, media_type }
+    pub(crate) unsafe fn from_stream(index: usize, stream: *const AVStream) -> Self {
+        let media_type = ffmpeg::media_type_of_stream(stream);
+        let codecpar = (*stream).codecpar.as_ref();
+        let codec_id = codecpar.map_or(AVCodecID::AV_CODEC_ID_NONE, |c| c.codec_id);
+        let time_base = (*stream).time_base;
+        StreamInfo {
+            index,
+            media_type,
+            codec_id: codec_id.into(),
+            codec_name: c_str(avcodec_get_name(codec_id)).unwrap_or_default(),
+            profile: codecpar.and_then(|c| c_str(avcodec_profile_name(codec_id, c.profile))),
+            bit_rate: codecpar
+                .map(|c| c.bit_rate)
+                .filter(|&b| b > 0)
+                .map(|b| b as u64),
+            time_base: time_base.into(),
+            duration: match (*stream).duration {
+                AV_NOPTS_VALUE => None,
+                d => Some(clock::pts_to_duration(d, time_base.into())),
+            },
+            frame_count: match (*stream).nb_frames {
+                n if n > 0 => Some(n as u64),
+                _ => None,
+            },
+            language: dict_get((*stream).metadata, c"language"),
+            video: (codecpar.is_some() && media_type == MediaType::Video)
+                .then(|| VideoInfo::from_stream(stream)),
+            audio: (codecpar.is_some() && media_type == MediaType::Audio)
+                .then(|| AudioInfo::from_stream(stream)),
+        }
     }
 
     pub fn index(&self) -> usize {
@@ -41,6 +362,155 @@ impl StreamInfo {
     pub fn media_type(&self) -> MediaType {
         self.media_type
     }
+
+    pub fn codec_id(&self) -> ffmpeg_next::codec::Id {
+        self.codec_id
+    }
+
+    pub fn codec_name(&self) -> &str {
+        &self.codec_name
+    }
+
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    pub fn bit_rate(&self) -> Option<u64> {
+        self.bit_rate
+    }
+
+    pub fn time_base(&self) -> ffmpeg_next::Rational {
+        self.time_base
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    /// Number of frames, if known from the container.
+    pub fn frame_count(&self) -> Option<u64> {
+        self.frame_count
+    }
+
+    pub fn language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
+
+    /// Properties of a video stream.
+    pub fn video(&self) -> Option<&VideoInfo> {
+        self.video.as_ref()
+    }
+
+    /// Properties of an audio stream.
+    pub fn audio(&self) -> Option<&AudioInfo> {
+        self.audio.as_ref()
+    }
+}
+
+/// Options for opening a `MediaSource`, for instance:
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use vtk::OpenOptions;
+/// let source = OpenOptions::new()
+///     .rtsp_transport("tcp")
+///     .timeout(Duration::from_secs(5))
+///     .open_url("rtsp://camera.local/stream");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct OpenOptions {
+    format: Option<String>,
+    options: Vec<(String, String)>,
+    token: Option<CancellationToken>,
+    operation_timeout: Option<Duration>,
+}
+
+impl OpenOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forces the input format, e.g. "mpegts", instead of probing it.
+    pub fn format(mut self, name: &str) -> Self {
+        self.format = Some(name.to_owned());
+        self
+    }
+
+    /// Sets an option of the demuxer or of the protocol, as accepted by
+    /// `avformat_open_input`. Options that are not recognized are logged
+    /// and ignored.
+    pub fn option(mut self, key: &str, value: &str) -> Self {
+        self.options.retain(|(k, _)| k != key);
+        self.options.push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Maximum number of bytes read to probe the format.
+    pub fn probe_size(self, bytes: u64) -> Self {
+        self.option("probesize", &bytes.to_string())
+    }
+
+    /// Maximum duration of the media read to find the stream properties.
+    pub fn analyze_duration(self, duration: Duration) -> Self {
+        self.option("analyzeduration", &duration.as_micros().to_string())
+    }
+
+    /// Lower transport of RTSP streams: "udp", "tcp", "udp_multicast" or
+    /// "http".
+    pub fn rtsp_transport(self, transport: &str) -> Self {
+        self.option("rtsp_transport", transport)
+    }
+
+    /// Timeout of the network operations of the protocol.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.option("timeout", &timeout.as_micros().to_string())
+    }
+
+    /// Aborts the opening and, later on, the reads of the source once
+    /// `token` is cancelled.
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    /// Maximum duration of each blocking operation on the source: opening
+    /// it, probing its streams, reading a packet or seeking. Operations that
+    /// take longer fail with `MediaError::TimedOut`. Unlike `timeout`, this
+    /// applies to any protocol, but it can not abort a read of a custom
+    /// reader.
+    pub fn operation_timeout(mut self, timeout: Duration) -> Self {
+        self.operation_timeout = Some(timeout);
+        self
+    }
+
+    pub fn open(&self, path: &Path) -> Result<MediaSource, MediaError> {
+        let path = path_to_cstring(path)?;
+        MediaSource::open_input(&path, None, self)
+    }
+
+    pub fn open_url(&self, url: &str) -> Result<MediaSource, MediaError> {
+        let url = CString::new(url).map_err(|_| MediaError::InvalidPath)?;
+        MediaSource::open_input(&url, None, self)
+    }
+
+    pub fn open_reader<R: Read + Seek + Send + 'static>(
+        &self,
+        reader: R,
+    ) -> Result<MediaSource, MediaError> {
+        let io = IoContext::new(Box::new(reader)).ok_or(MediaError::from(AVERROR(ENOMEM)))?;
+        MediaSource::open_input(c"", Some(io), self)
+    }
+
+    fn dictionary(&self) -> Result<ffmpeg_next::Dictionary<'static>, MediaError> {
+        let mut dictionary = ffmpeg_next::Dictionary::new();
+        for (key, value) in &self.options {
+            if key.contains('\0') || value.contains('\0') {
+                return Err(AVERROR(EINVAL).into());
+            }
+            dictionary.set(key, value);
+        }
+        Ok(dictionary)
+    }
 }
 
 pub struct MediaSource {
@@ -48,25 +518,70 @@ pub struct MediaSource {
 }
 
 impl MediaSource {
-    pub fn open(path: &Path) -> Self {
-        let path = CString::new(path.as_os_str().to_str().unwrap()).unwrap();
+    pub fn open(path: &Path) -> Result<Self, MediaError> {
+        OpenOptions::new().open(path)
+    }
+
+    /// Opens a URL, e.g. "http://...", "rtsp://...", "udp://..." or
+    /// "file:...".
+    pub fn open_url(url: &str) -> Result<Self, MediaError> {
+        OpenOptions::new().open_url(url)
+    }
+
+    /// Opens media read from `reader`, e.g. a decrypting stream or an object
+    /// fetched from storage. The format is probed from the content.
+    pub fn from_reader<R: Read + Seek + Send + 'static>(reader: R) -> Result<Self, MediaError> {
+        OpenOptions::new().open_reader(reader)
+    }
+
+    /// Opens media held in memory.
+    pub fn from_bytes<B: AsRef<[u8]> + Send + 'static>(bytes: B) -> Result<Self, MediaError> {
+        Self::from_reader(io::Cursor::new(bytes))
+    }
+
+    fn open_input(
+        url: &CStr,
+        io: Option<IoContext>,
+        options: &OpenOptions,
+    ) -> Result<Self, MediaError> {
         unsafe {
-            let mut context = ptr::null_mut();
-            match avformat_open_input(
-                &mut context,
-                path.as_ptr(),
-                ptr::null_mut(),
-                ptr::null_mut(),
-            ) {
-                0 => (),
-                e => panic!("Error: {}", e),
-            }
+            let format = match options.format.as_deref() {
+                Some(name) => {
+                    let name = CString::new(name).map_err(|_| MediaError::UnsupportedFormat)?;
+                    match av_find_input_format(name.as_ptr()) {
+                        f if f.is_null() => return Err(MediaError::UnsupportedFormat),
+                        f => f,
+                    }
+                }
+                None => ptr::null(),
+            };
+            let mut dictionary = options.dictionary()?.disown();
+            let interrupt = Interrupt::new(options.token.clone(), options.operation_timeout);
 
-            let context = ffmpeg::context::InputFormat::wrap(context);
-            match avformat_find_stream_info(context.as_mut_ptr(), ptr::null_mut()) {
-                r if r >= 0 => MediaSource { context },
-                e => panic!("Error: {}", e),
+            let mut context = avformat_alloc_context();
+            if context.is_null() {
+                ffmpeg_next::Dictionary::own(dictionary);
+                return Err(AVERROR(ENOMEM).into());
+            }
+            (*context).interrupt_callback = interrupt.callback();
+            if let Some(io) = io.as_ref() {
+                (*context).pb = io.as_mut_ptr();
+                (*context).flags |= AVFMT_FLAG_CUSTOM_IO;
             }
+            // The context is freed on failure.
+            let result = interrupt
+                .run(|| avformat_open_input(&mut context, url.as_ptr(), format, &mut dictionary));
+            // Whatever is left was not recognized by the demuxer or protocol.
+            for (key, _) in ffmpeg_next::Dictionary::own(dictionary).iter() {
+                log::warn!("Unused option: {}", key);
+            }
+            result?;
+
+            let context = ffmpeg::context::InputFormat::wrap(context, io, interrupt);
+            context
+                .interrupt()
+                .run(|| avformat_find_stream_info(context.as_mut_ptr(), ptr::null_mut()))?;
+            Ok(MediaSource { context })
         }
     }
 
@@ -74,14 +589,56 @@ impl MediaSource {
         unsafe { Format::wrap((*self.context.as_ptr()).iformat as *mut AVInputFormat) }
     }
 
+    /// Duration of the whole media, if known.
+    pub fn duration(&self) -> Option<Duration> {
+        match unsafe { (*self.context.as_ptr()).duration } {
+            AV_NOPTS_VALUE => None,
+            d => Some(clock::pts_to_duration(d, ffmpeg_next::rescale::TIME_BASE)),
+        }
+    }
+
+    /// Timestamp of the first frame, if known. Negative start times are
+    /// clamped to zero.
+    pub fn start_time(&self) -> Option<Duration> {
+        match unsafe { (*self.context.as_ptr()).start_time } {
+            AV_NOPTS_VALUE => None,
+            t => Some(clock::pts_to_duration(t, ffmpeg_next::rescale::TIME_BASE)),
+        }
+    }
+
+    /// Overall bit rate in bits per second, if known.
+    pub fn bit_rate(&self) -> Option<u64> {
+        match unsafe { (*self.context.as_ptr()).bit_rate } {
+            b if b > 0 => Some(b as u64),
+            _ => None,
+        }
+    }
+
+    /// Metadata of the container (title, artist, creation_time, encoder...),
+    /// in the order it was read.
+    pub fn metadata(&self) -> Vec<(String, String)> {
+        unsafe { dict_entries((*self.context.as_ptr()).metadata) }
+    }
+
+    pub fn metadata_value(&self, key: &str) -> Option<String> {
+        let key = CString::new(key).ok()?;
+        unsafe { dict_get((*self.context.as_ptr()).metadata, &key) }
+    }
+
+    pub fn chapters(&self) -> Vec<Chapter> {
+        unsafe {
+            let context = &*self.context.as_ptr();
+            (0..context.nb_chapters as usize)
+                .map(|i| Chapter::from_chapter(*context.chapters.add(i)))
+                .collect()
+        }
+    }
+
     pub fn all_streams(&self) -> Vec<StreamInfo> {
         unsafe {
             let nb_streams = (*self.context.as_ptr()).nb_streams as usize;
             (0..nb_streams)
-                .map(|i| {
-                    let stream = *(*self.context.as_ptr()).streams.add(i);
-                    StreamInfo::new(i, ffmpeg::media_type_of_stream(stream))
-                })
+                .map(|i| StreamInfo::from_stream(i, *(*self.context.as_ptr()).streams.add(i)))
                 .collect()
         }
     }
@@ -93,7 +650,7 @@ impl MediaSource {
                 return None;
             }
             let stream = *(*self.context.as_ptr()).streams.add(index);
-            Some(StreamInfo::new(index, ffmpeg::media_type_of_stream(stream)))
+            Some(StreamInfo::from_stream(index, stream))
         }
     }
 
@@ -116,3 +673,175 @@ impl MediaSource {
         self.context
     }
 }
+
+#[cfg(unix)]
+fn path_to_cstring(path: &Path) -> Result<CString, MediaError> {
+    use std::os::unix::ffi::OsStrExt;
+    CString::new(path.as_os_str().as_bytes()).map_err(|_| MediaError::InvalidPath)
+}
+
+#[cfg(not(unix))]
+fn path_to_cstring(path: &Path) -> Result<CString, MediaError> {
+    let path = path.to_str().ok_or(MediaError::InvalidPath)?;
+    CString::new(path).map_err(|_| MediaError::InvalidPath)
+}
+
+unsafe fn c_str(ptr: *const c_char) -> Option<String> {
+    if ptr.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(ptr).to_string_lossy().into_owned())
+    }
+}
+
+unsafe fn dict_get(dict: *const AVDictionary, key: &CStr) -> Option<String> {
+    let entry = av_dict_get(dict, key.as_ptr(), ptr::null(), 0);
+    entry.as_ref().and_then(|entry| c_str(entry.value))
+}
+
+unsafe fn dict_entries(dict: *const AVDictionary) -> Vec<(String, String)> {
+    let mut entries = Vec::new();
+    let mut entry = ptr::null_mut();
+    loop {
+        entry = av_dict_get(dict, c"".as_ptr(), entry, AV_DICT_IGNORE_SUFFIX);
+        let Some(e) = entry.as_ref() else {
+            return entries;
+        };
+        if let (Some(key), Some(value)) = (c_str(e.key), c_str(e.value)) {
+            entries.push((key, value));
+        }
+    }
+}
+
+unsafe fn display_matrix(codecpar: &AVCodecParameters) -> Option<[i32; 9]> {
+    let side_data = av_packet_side_data_get(
+        codecpar.coded_side_data,
+        codecpar.nb_coded_side_data,
+        AVPacketSideDataType::AV_PKT_DATA_DISPLAYMATRIX,
+    )
+    .as_ref()?;
+    if side_data.size < std::mem::size_of::<[i32; 9]>() {
+        return None;
+    }
+    Some(ptr::read_unaligned(side_data.data as *const [i32; 9]))
+}
+
+/// Converts the raw format of video codec parameters. Formats that are not
+/// declared by the bindings, e.g. those of a newer libavutil, are `None`.
+fn pixel_format(value: c_int) -> ffmpeg_next::format::Pixel {
+    // The formats are numbered from `NONE` up to `NB` without gaps, so any
+    // value in this range is a declared variant.
+    if (AVPixelFormat::AV_PIX_FMT_NONE as c_int..AVPixelFormat::AV_PIX_FMT_NB as c_int)
+        .contains(&value)
+    {
+        unsafe { std::mem::transmute::<c_int, AVPixelFormat>(value) }.into()
+    } else {
+        ffmpeg_next::format::Pixel::None
+    }
+}
+
+/// Converts the raw format of audio codec parameters, like `pixel_format`.
+fn sample_format(value: c_int) -> ffmpeg_next::format::Sample {
+    if (AVSampleFormat::AV_SAMPLE_FMT_NONE as c_int..AVSampleFormat::AV_SAMPLE_FMT_NB as c_int)
+        .contains(&value)
+    {
+        unsafe { std::mem::transmute::<c_int, AVSampleFormat>(value) }.into()
+    } else {
+        ffmpeg_next::format::Sample::None
+    }
+}
+
+fn to_rational(value: AVRational) -> Option<ffmpeg_next::Rational> {
+    if value.num == 0 || value.den == 0 {
+        None
+    } else {
+        Some(value.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Orientation of a display matrix rotating clockwise by `rotation`
+    /// degrees, after the given flips.
+    fn orientation_of(rotation: f64, flip_horizontal: bool, flip_vertical: bool) -> Orientation {
+        let mut matrix = [0i32; 9];
+        unsafe {
+            av_display_rotation_set(matrix.as_mut_ptr(), rotation);
+            av_display_matrix_flip(
+                matrix.as_mut_ptr(),
+                flip_horizontal as c_int,
+                flip_vertical as c_int,
+            );
+            Orientation::from_display_matrix(&matrix)
+        }
+    }
+
+    #[test]
+    fn rotations() {
+        assert_eq!(orientation_of(0.0, false, false), Orientation::default());
+        assert_eq!(
+            orientation_of(90.0, false, false),
+            Orientation::new(90, false, false)
+        );
+        // Like the ffmpeg tool, half turns are made of both flips.
+        assert_eq!(
+            orientation_of(180.0, false, false),
+            Orientation::new(0, true, true)
+        );
+        assert_eq!(
+            orientation_of(270.0, false, false),
+            Orientation::new(270, false, false)
+        );
+        assert_eq!(
+            orientation_of(-90.0, false, false),
+            Orientation::new(270, false, false)
+        );
+    }
+
+    #[test]
+    fn flips() {
+        assert_eq!(
+            orientation_of(0.0, true, false),
+            Orientation::new(0, true, false)
+        );
+        assert_eq!(
+            orientation_of(0.0, false, true),
+            Orientation::new(0, false, true)
+        );
+        assert_eq!(
+            orientation_of(0.0, true, true),
+            Orientation::new(0, true, true)
+        );
+    }
+
+    #[test]
+    fn transpositions() {
+        // A quarter turn with a mirrored x axis is a transpose, with
+        // `matrix[3] > 0`.
+        let transpose = orientation_of(90.0, true, false);
+        assert_eq!(transpose, Orientation::new(90, false, true));
+        // A three quarter turn with a mirrored x axis is an anti-transpose,
+        // with `matrix[3] < 0`.
+        let anti_transpose = orientation_of(270.0, true, false);
+        assert_eq!(anti_transpose, Orientation::new(270, false, true));
+        assert!(transpose.is_transposed() && anti_transpose.is_transposed());
+    }
+
+    #[test]
+    fn invalid_matrix() {
+        assert_eq!(
+            unsafe { Orientation::from_display_matrix(&[0; 9]) },
+            Orientation::default()
+        );
+    }
+
+    #[test]
+    fn new_rounds_to_quarter_turns() {
+        assert_eq!(Orientation::new(-90, false, false).rotation(), 270);
+        assert_eq!(Orientation::new(450, false, false).rotation(), 90);
+        assert_eq!(Orientation::new(44, false, false).rotation(), 0);
+        assert_eq!(Orientation::new(46, false, false).rotation(), 90);
+    }
+}
@@ -0,0 +1,425 @@
+use std::{
+    io,
+    sync::{mpsc, Arc, Condvar, Mutex, MutexGuard},
+    thread,
+    time::Duration,
+};
+use winit::{dpi::PhysicalSize, window::Window};
+
+extern crate ffmpeg_next as ffmpeg;
+
+use crate::{
+    audio_decoder::AudioDecoder,
+    audio_sink::AudioSink,
+    clock::{pts_to_duration, Clock, ClockMode, FrameScheduler, Schedule},
+    demuxer::{Demuxer, SeekMode},
+    video_decoder::VideoDecoder,
+    video_renderer::{CropRect, ScaleFilter, ScaleMode, VideoRenderer},
+    MediaError, MediaSource, MediaType, Orientation,
+};
+
+type VideoSender = mpsc::SyncSender<ffmpeg::frame::Video>;
+type EventHandler = Box<dyn Fn(PlayerEvent) + Send>;
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum PlayerState {
+    Stopped,
+    Playing,
+    Paused,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum PlayerEvent {
+    StateChanged(PlayerState),
+    /// All the streams have been played to the end. The player is stopped
+    /// and starts over on the next `play`.
+    EndOfStream,
+    Error(MediaError),
+}
+
+/// Plays the best video stream and, given an `AudioSink`, the best audio
+/// stream of a `MediaSource`.
+///
+/// Decoding runs on worker threads. The video is presented by a
+/// `VideoRenderer`, which is driven by the window through `init`, `render`
+/// and `resize`.
+pub struct Player {
+    shared: Arc<PlayerShared>,
+    demuxer: Demuxer,
+    clock: Clock,
+    renderer: VideoRenderer,
+    // Orientation of the video stream, unless overridden.
+    orientation: Orientation,
+}
+
+impl Player {
+    pub fn new(
+        source: MediaSource,
+        audio_sink: Option<Box<dyn AudioSink>>,
+    ) -> Result<Self, MediaError> {
+        let video_stream = source.best_stream(MediaType::Video);
+        let video_index = video_stream.as_ref().map(|s| s.index());
+        let orientation = video_stream
+            .as_ref()
+            .and_then(|s| s.video())
+            .map(|v| v.orientation())
+            .unwrap_or_default();
+        let audio_index = audio_sink
+            .as_ref()
+            .and_then(|_| source.best_stream(MediaType::Audio))
+            .map(|s| s.index());
+        if video_index.is_none() && audio_index.is_none() {
+            return Err(MediaError::UnsupportedFormat);
+        }
+
+        let demuxer = Demuxer::new(source);
+        let video_decoder = match video_index.and_then(|i| demuxer.stream(i)) {
+            Some(stream) => Some(VideoDecoder::new(stream)?),
+            None => None,
+        };
+        let audio = match (audio_index.and_then(|i| demuxer.stream(i)), audio_sink) {
+            (Some(stream), Some(sink)) => Some((AudioDecoder::new(stream, sink.format())?, sink)),
+            _ => None,
+        };
+
+        let clock = Clock::new(if audio.is_some() {
+            ClockMode::AudioMaster
+        } else {
+            ClockMode::ExternalClock
+        });
+        let shared = Arc::new(PlayerShared {
+            control: Mutex::new(Control {
+                state: PlayerState::Stopped,
+                serial: 0,
+                workers: video_decoder.is_some() as usize + audio.is_some() as usize,
+                ended: 0,
+                rewind: false,
+                quit: false,
+            }),
+            cond: Condvar::new(),
+            event_handler: Mutex::new(None),
+        });
+
+        let (video_sender, video_receiver) = mpsc::sync_channel(1);
+        if let Some(decoder) = video_decoder {
+            let shared = shared.clone();
+            let scheduler = FrameScheduler::new(clock.clone(), decoder.time_base());
+            thread::spawn(move || video_loop(shared, decoder, scheduler, video_sender));
+        }
+        if let Some((decoder, sink)) = audio {
+            let shared = shared.clone();
+            let clock = clock.clone();
+            thread::spawn(move || audio_loop(shared, decoder, sink, clock));
+        }
+
+        let mut renderer = VideoRenderer::new(video_receiver);
+        renderer.set_orientation(orientation);
+        Ok(Player {
+            shared,
+            demuxer,
+            clock,
+            renderer,
+            orientation,
+        })
+    }
+
+    /// Sets the handler of the player events. It is called on the worker
+    /// threads.
+    pub fn set_event_handler(&mut self, handler: EventHandler) {
+        *self.shared.event_handler.lock().unwrap() = Some(handler);
+    }
+
+    pub fn state(&self) -> PlayerState {
+        self.shared.lock().state
+    }
+
+    /// Current media time, or `None` before the first frame after a start or
+    /// a seek has been played.
+    pub fn position(&self) -> Option<Duration> {
+        self.clock.time()
+    }
+
+    pub fn play(&mut self) -> Result<(), MediaError> {
+        let (state, rewind) = {
+            let control = self.shared.lock();
+            (control.state, control.rewind)
+        };
+        if state == PlayerState::Playing {
+            return Ok(());
+        }
+        if state == PlayerState::Stopped && rewind {
+            self.seek(Duration::ZERO)?;
+        }
+        self.clock.resume();
+        self.shared.set_state(PlayerState::Playing);
+        Ok(())
+    }
+
+    pub fn pause(&mut self) {
+        if self.state() == PlayerState::Playing {
+            self.clock.pause();
+            self.shared.set_state(PlayerState::Paused);
+        }
+    }
+
+    /// Stops the playback. The next `play` starts over from the beginning.
+    pub fn stop(&mut self) {
+        if self.state() != PlayerState::Stopped {
+            self.clock.pause();
+            self.shared.lock().rewind = true;
+            self.shared.set_state(PlayerState::Stopped);
+        }
+    }
+
+    /// Seeks accurately to `position`. The state of the player is kept.
+    pub fn seek(&mut self, position: Duration) -> Result<(), MediaError> {
+        self.seek_with_mode(position, SeekMode::Accurate)
+    }
+
+    pub fn seek_with_mode(&mut self, position: Duration, mode: SeekMode) -> Result<(), MediaError> {
+        self.demuxer.seek(position, mode)?;
+        {
+            let mut control = self.shared.lock();
+            control.serial += 1;
+            control.ended = 0;
+            control.rewind = false;
+            self.clock.reset();
+        }
+        self.shared.cond.notify_all();
+        Ok(())
+    }
+
+    pub fn init(
+        &mut self,
+        window: Arc<Window>,
+        request_redraw: Box<dyn Fn() + Send>,
+    ) -> anyhow::Result<()> {
+        self.renderer.init(window, request_redraw)
+    }
+
+    pub fn render(&mut self) {
+        self.renderer.render();
+    }
+
+    /// Overrides the rotation and flips of the video, `None` restores the
+    /// orientation of the stream.
+    pub fn set_orientation(&mut self, orientation: Option<Orientation>) {
+        self.renderer
+            .set_orientation(orientation.unwrap_or(self.orientation));
+    }
+
+    /// Restricts the video to `crop`, e.g. to cut burned-in letterbox bars
+    /// off. `None` presents it whole.
+    pub fn set_crop(&mut self, crop: Option<CropRect>) {
+        self.renderer.set_crop(crop);
+    }
+
+    /// Selects how the video is scaled into the window.
+    pub fn set_scale_mode(&mut self, mode: ScaleMode) {
+        self.renderer.set_scale_mode(mode);
+    }
+
+    /// Selects the filter the video is scaled with.
+    pub fn set_scale_filter(&mut self, filter: ScaleFilter) {
+        self.renderer.set_scale_filter(filter);
+    }
+
+    pub fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.renderer.resize(size);
+    }
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        // The workers exit on their own once they notice, they are not joined
+        // since they may be blocked on the renderer. Cancelling the demuxer
+        // unblocks those waiting for the input.
+        self.shared.lock().quit = true;
+        self.shared.cond.notify_all();
+        self.demuxer.cancel();
+    }
+}
+
+struct Control {
+    state: PlayerState,
+    // Incremented by every seek, so that the workers drop what they decoded
+    // before it.
+    serial: u64,
+    workers: usize,
+    // Number of workers that reached the end of their stream.
+    ended: usize,
+    rewind: bool,
+    quit: bool,
+}
+
+struct PlayerShared {
+    control: Mutex<Control>,
+    cond: Condvar,
+    event_handler: Mutex<Option<EventHandler>>,
+}
+
+impl PlayerShared {
+    fn lock(&self) -> MutexGuard<'_, Control> {
+        self.control.lock().unwrap()
+    }
+
+    fn emit(&self, event: PlayerEvent) {
+        if let Some(handler) = self.event_handler.lock().unwrap().as_ref() {
+            handler(event);
+        }
+    }
+
+    /// Reports an error met by a worker, unless the player is dropped, which
+    /// cancels the pending reads.
+    fn error(&self, error: MediaError) {
+        if error != MediaError::Cancelled || !self.lock().quit {
+            self.emit(PlayerEvent::Error(error));
+        }
+    }
+
+    fn set_state(&self, state: PlayerState) {
+        {
+            let mut control = self.lock();
+            if control.state == state {
+                return;
+            }
+            control.state = state;
+        }
+        self.cond.notify_all();
+        self.emit(PlayerEvent::StateChanged(state));
+    }
+
+    fn is_playing(&self) -> bool {
+        self.lock().state == PlayerState::Playing
+    }
+
+    /// Whether the data a worker got under `serial` is still to be played.
+    fn is_current(&self, serial: u64) -> bool {
+        let control = self.lock();
+        !control.quit && control.serial == serial
+    }
+
+    /// Blocks while the player is not playing. Returns the current serial, or
+    /// `None` when the player is dropped.
+    fn wait_until_playing(&self) -> Option<u64> {
+        let mut control = self.lock();
+        while !control.quit && control.state != PlayerState::Playing {
+            control = self.cond.wait(control).unwrap();
+        }
+        (!control.quit).then_some(control.serial)
+    }
+
+    /// Called by a worker at the end of its stream. Blocks until the next seek
+    /// or until the player is dropped.
+    fn end_of_stream(&self, serial: u64) {
+        let mut control = self.lock();
+        if control.serial != serial {
+            return;
+        }
+        control.ended += 1;
+        if control.ended == control.workers {
+            control.state = PlayerState::Stopped;
+            control.rewind = true;
+            drop(control);
+            self.cond.notify_all();
+            self.emit(PlayerEvent::EndOfStream);
+            self.emit(PlayerEvent::StateChanged(PlayerState::Stopped));
+            control = self.lock();
+        }
+        while !control.quit && control.serial == serial {
+            control = self.cond.wait(control).unwrap();
+        }
+    }
+}
+
+fn video_loop(
+    shared: Arc<PlayerShared>,
+    mut decoder: VideoDecoder,
+    scheduler: FrameScheduler,
+    sender: VideoSender,
+) {
+    while let Some(serial) = shared.wait_until_playing() {
+        match decoder.decode() {
+            Ok(Some(frame)) => loop {
+                if !shared.is_current(serial) {
+                    break;
+                }
+                match scheduler.check(frame.pts()) {
+                    Schedule::Present => {
+                        if sender.send(frame).is_err() {
+                            return;
+                        }
+                        break;
+                    }
+                    Schedule::Drop => break,
+                    Schedule::Wait(duration) => thread::sleep(duration),
+                }
+            },
+            Ok(None) => shared.end_of_stream(serial),
+            Err(e) => {
+                shared.error(e);
+                shared.end_of_stream(serial);
+            }
+        }
+    }
+}
+
+fn audio_loop(
+    shared: Arc<PlayerShared>,
+    mut decoder: AudioDecoder,
+    mut sink: Box<dyn AudioSink>,
+    clock: Clock,
+) {
+    let time_base = decoder.time_base();
+    let mut written = Duration::ZERO;
+    loop {
+        if !shared.is_playing() {
+            sink.pause();
+        }
+        let Some(serial) = shared.wait_until_playing() else {
+            break;
+        };
+        sink.resume();
+
+        match decoder.decode() {
+            Ok(Some(buffer)) => {
+                if !shared.is_current(serial) {
+                    continue;
+                }
+                let start = written;
+                if let Err(e) = sink.write(&buffer) {
+                    shared.emit(PlayerEvent::Error(sink_error(e)));
+                    clock.end_audio();
+                    shared.end_of_stream(serial);
+                    continue;
+                }
+                written += buffer.duration();
+
+                // The buffer started playing at `start` on the sink.
+                if let Some(pts) = buffer.pts() {
+                    if clock.mode() == ClockMode::AudioMaster && shared.is_current(serial) {
+                        let played = sink.position().saturating_sub(start);
+                        clock.set(pts_to_duration(pts, time_base) + played);
+                    }
+                }
+            }
+            Ok(None) => {
+                clock.end_audio();
+                let _ = sink.flush();
+                shared.end_of_stream(serial);
+            }
+            Err(e) => {
+                shared.error(e);
+                clock.end_audio();
+                shared.end_of_stream(serial);
+            }
+        }
+    }
+}
+
+/// Reports a failure of the audio sink with its errno, `EIO` if it has none.
+fn sink_error(e: io::Error) -> MediaError {
+    MediaError::Other(ffmpeg::Error::Other {
+        errno: e.raw_os_error().unwrap_or(ffmpeg::error::EIO),
+    })
+}
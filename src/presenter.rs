@@ -2,7 +2,7 @@ use std::rc::Rc;
 
 use wgpu::util::DeviceExt;
 
-use crate::{texture::Texture, wgpu_context::WgpuContext};
+use crate::{texture::Texture, wgpu_context::WgpuContext, Orientation};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -55,35 +55,324 @@ const VERTICES: &[Vertex] = &[
 
 const INDICES: &[u16] = &[0, 1, 2, 2, 3, 0];
 
-fn calc_view_port(src_size: (u32, u32), dst_size: (u32, u32)) -> (f32, f32, f32, f32) {
-    let (src_width, src_height) = src_size;
-    let (dst_width, dst_height) = dst_size;
-    let src_aspect = src_width as f32 / src_height as f32;
-    let dst_aspect = dst_width as f32 / dst_height as f32;
-    let (mut x, mut y, mut width, mut height) = (0.0, 0.0, dst_size.0 as f32, dst_size.1 as f32);
-    if src_aspect > dst_aspect {
-        height = dst_width as f32 / src_aspect;
-        y = (dst_height as f32 - height) / 2.0;
-    } else if src_aspect < dst_aspect {
-        width = dst_height as f32 * src_aspect;
-        x = (dst_width as f32 - width) / 2.0;
-    } else {
-        // Do nothing
+/// How frames are scaled into the target of a `Presenter`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ScaleMode {
+    /// Shows the whole frame, letterboxed to keep its display aspect ratio,
+    /// i.e. with the sample aspect ratio applied.
+    #[default]
+    Fit,
+    /// Fills the target keeping the display aspect ratio, the frame is
+    /// cropped.
+    Fill,
+    /// Fills the target, ignoring the aspect ratio.
+    Stretch,
+    /// One frame pixel per target pixel, centered. The sample aspect ratio
+    /// is ignored.
+    Native,
+    /// `factor` times the native size, with the center of the frame moved
+    /// by `pan`, in target pixels, from the center of the target. Like
+    /// `Native`, the sample aspect ratio is ignored.
+    Zoom { factor: f32, pan: (f32, f32) },
+}
+
+/// Area of the frames to present, in pixels of their visible area, e.g. to
+/// cut burned-in letterbox bars off.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// `crop` clamped to a frame of `size`, the whole frame if it is outside.
+fn clamp_crop(crop: Option<CropRect>, size: (u32, u32)) -> CropRect {
+    let (width, height) = size;
+    match crop {
+        Some(crop) if crop.x < width && crop.y < height && crop.width > 0 && crop.height > 0 => {
+            CropRect {
+                width: crop.width.min(width - crop.x),
+                height: crop.height.min(height - crop.y),
+                ..crop
+            }
+        }
+        _ => CropRect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        },
+    }
+}
+
+/// Filter resampling frames to the size they are presented at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScaleFilter {
+    /// Bilinear, between mipmaps when downscaling.
+    #[default]
+    Bilinear,
+    /// Bicubic, with the Catmull-Rom spline.
+    Bicubic,
+    Lanczos2,
+    Lanczos3,
+}
+
+impl ScaleFilter {
+    fn index(&self) -> u32 {
+        match self {
+            ScaleFilter::Bilinear => 0,
+            ScaleFilter::Bicubic => 1,
+            ScaleFilter::Lanczos2 => 2,
+            ScaleFilter::Lanczos3 => 3,
+        }
+    }
+}
+
+/// Area of the target the frame is drawn in, along with the part of the
+/// frame visible there, in coordinates of the displayed (rotated) frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ViewPort {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    tex_offset: [f32; 2],
+    tex_scale: [f32; 2],
+}
+
+fn calc_view_port(
+    src_size: (u32, u32),
+    sample_aspect_ratio: f32,
+    orientation: Orientation,
+    dst_size: (u32, u32),
+    mode: ScaleMode,
+) -> Option<ViewPort> {
+    // Size of the frame in pixels, and with square pixels.
+    let (mut native_width, mut native_height) = (src_size.0 as f32, src_size.1 as f32);
+    let mut src_width = native_width * sample_aspect_ratio;
+    let mut src_height = native_height;
+    if orientation.is_transposed() {
+        std::mem::swap(&mut native_width, &mut native_height);
+        std::mem::swap(&mut src_width, &mut src_height);
+    }
+    let dst_width = dst_size.0 as f32;
+    let dst_height = dst_size.1 as f32;
+    let fit = (dst_width / src_width).min(dst_height / src_height);
+    let fill = (dst_width / src_width).max(dst_height / src_height);
+    let (width, height, pan) = match mode {
+        ScaleMode::Fit => (src_width * fit, src_height * fit, (0.0, 0.0)),
+        ScaleMode::Fill => (src_width * fill, src_height * fill, (0.0, 0.0)),
+        ScaleMode::Stretch => (dst_width, dst_height, (0.0, 0.0)),
+        ScaleMode::Native => (native_width, native_height, (0.0, 0.0)),
+        ScaleMode::Zoom { factor, pan } => (native_width * factor, native_height * factor, pan),
+    };
+    let x = (dst_width - width) / 2.0 + pan.0;
+    let y = (dst_height - height) / 2.0 + pan.1;
+
+    // The viewport must lie within the target, so the frame is clipped.
+    let (x0, y0) = (x.max(0.0), y.max(0.0));
+    let (x1, y1) = ((x + width).min(dst_width), (y + height).min(dst_height));
+    if x1 <= x0 || y1 <= y0 {
+        return None;
+    }
+    Some(ViewPort {
+        x: x0,
+        y: y0,
+        width: x1 - x0,
+        height: y1 - y0,
+        tex_offset: [(x0 - x) / width, (y0 - y) / height],
+        tex_scale: [(x1 - x0) / width, (y1 - y0) / height],
+    })
+}
+
+/// Last texel in mip `level` of a crop ending before texel `end` of a
+/// texture `size` texels wide at level 0.
+fn crop_end(end: u32, size: u32, level: u32) -> u32 {
+    let level_size = (size >> level).max(1);
+    end.div_ceil(1 << level).min(level_size) - 1
+}
+
+/// Texture coordinates of the point at `(u, v)` in the displayed frame.
+fn oriented_tex_coords(orientation: Orientation, (u, v): (f32, f32)) -> [f32; 2] {
+    let (x, y) = match orientation.rotation() {
+        90 => (v, 1.0 - u),
+        180 => (1.0 - u, 1.0 - v),
+        270 => (1.0 - v, u),
+        _ => (u, v),
+    };
+    [
+        if orientation.flip_horizontal() {
+            1.0 - x
+        } else {
+            x
+        },
+        if orientation.flip_vertical() {
+            1.0 - y
+        } else {
+            y
+        },
+    ]
+}
+
+/// Maps the texture coordinates of the quad to the visible part of the
+/// displayed frame, then to the texture through the orientation and the
+/// crop.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Transform {
+    tex_offset: [f32; 2],
+    tex_scale: [f32; 2],
+    // Columns of the linear part of the mapping to the texture.
+    to_texture: [[f32; 2]; 2],
+    to_texture_offset: [f32; 2],
+    filter_index: u32,
+    // Mip level the kernel filters sample.
+    level: u32,
+    // First and last texels of the crop in that level.
+    crop_min: [u32; 2],
+    crop_max: [u32; 2],
+}
+
+const MIP_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Mipmaps of the frame, to downscale it without aliasing. Level 0 is a
+/// copy of the frame, each following level is half the previous one.
+struct MipChain {
+    texture: wgpu::Texture,
+    // All the levels, sampled when presenting.
+    view: wgpu::TextureView,
+    // One view per level, rendered to in turn.
+    level_views: Vec<wgpu::TextureView>,
+    // Bind groups sampling each level but the last, to render the next one.
+    level_bind_groups: Vec<wgpu::BindGroup>,
+    // Texture and generation the levels were generated from.
+    source: Option<(wgpu::Id<wgpu::Texture>, u64)>,
+}
+
+impl MipChain {
+    fn new(
+        context: &WgpuContext,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let level_count = u32::BITS - width.max(height).leading_zeros();
+        let texture = context.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("mip_chain"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: MIP_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let level_views: Vec<_> = (0..level_count)
+            .map(|level| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    base_mip_level: level,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let level_bind_groups = level_views[..level_views.len() - 1]
+            .iter()
+            .map(|view| new_source_bind_group(context, layout, view, sampler))
+            .collect();
+
+        MipChain {
+            texture,
+            view,
+            level_views,
+            level_bind_groups,
+            source: None,
+        }
+    }
+
+    fn level_count(&self) -> u32 {
+        self.level_views.len() as u32
     }
-    (x, y, width, height)
+}
+
+fn new_source_bind_group(
+    context: &WgpuContext,
+    layout: &wgpu::BindGroupLayout,
+    view: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    context
+        .device
+        .create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+            label: Some("mip_source_bind_group"),
+        })
+}
+
+const TARGET_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+enum Target {
+    Surface {
+        surface: wgpu::Surface<'static>,
+        config: wgpu::SurfaceConfiguration,
+    },
+    /// An owned texture, which can be read back.
+    Offscreen(Texture),
+}
+
+fn new_offscreen_texture(
+    context: &WgpuContext,
+    width: u32,
+    height: u32,
+) -> anyhow::Result<Texture> {
+    let usage = wgpu::TextureUsages::RENDER_ATTACHMENT
+        | wgpu::TextureUsages::COPY_SRC
+        | wgpu::TextureUsages::TEXTURE_BINDING;
+    Texture::new(
+        &context.device,
+        TARGET_FORMAT,
+        usage,
+        width.max(1),
+        height.max(1),
+    )
 }
 
 pub(crate) struct Presenter {
     context: Rc<WgpuContext>,
-    surface: wgpu::Surface<'static>,
-    surface_config: wgpu::SurfaceConfiguration,
+    target: Target,
     pipeline: wgpu::RenderPipeline,
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
     num_indices: u32,
     bind_group_layout: wgpu::BindGroupLayout,
     sampler: wgpu::Sampler,
+    transform: wgpu::Buffer,
+    mip_pipeline: wgpu::RenderPipeline,
+    mip_bind_group_layout: wgpu::BindGroupLayout,
+    mip_chain: Option<MipChain>,
     bkg_color: wgpu::Color,
+    scale_mode: ScaleMode,
+    filter: ScaleFilter,
+    sample_aspect_ratio: f32,
+    orientation: Orientation,
+    crop: Option<CropRect>,
 }
 
 impl Presenter {
@@ -92,16 +381,33 @@ impl Presenter {
         surface: wgpu::Surface<'static>,
         width: u32,
         height: u32,
-    ) -> Self {
+    ) -> anyhow::Result<Self> {
         let width = width.max(1);
         let height = height.max(1);
-        let mut surface_config = surface
+        let mut config = surface
             .get_default_config(&context.adapter, width, height)
-            .unwrap();
-        surface_config.format = wgpu::TextureFormat::Rgba8Unorm;
-        println!("Surface config: {:?}", surface_config);
-        surface.configure(&context.device, &surface_config);
+            .ok_or_else(|| anyhow::anyhow!("Surface is not supported by the adapter"))?;
+        config.format = TARGET_FORMAT;
+        println!("Surface config: {:?}", config);
+        surface.configure(&context.device, &config);
+        Ok(Self::with_target(
+            context,
+            Target::Surface { surface, config },
+        ))
+    }
 
+    /// Creates a presenter drawing into an owned texture instead of a
+    /// window, see `read_pixels`.
+    pub fn new_offscreen(
+        context: Rc<WgpuContext>,
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<Self> {
+        let texture = new_offscreen_texture(&context, width, height)?;
+        Ok(Self::with_target(context, Target::Offscreen(texture)))
+    }
+
+    fn with_target(context: Rc<WgpuContext>, target: Target) -> Self {
         let shader = context
             .device
             .create_shader_module(wgpu::ShaderModuleDescriptor {
@@ -149,6 +455,16 @@ impl Presenter {
                             ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                             count: None,
                         },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
                     ],
                     label: Some("source_texture_bind_group_layout"),
                 });
@@ -162,71 +478,341 @@ impl Presenter {
                     push_constant_ranges: &[],
                 });
 
-        let pipeline = context
+        let create_pipeline = |shader: &wgpu::ShaderModule,
+                               layout: &wgpu::PipelineLayout,
+                               format: wgpu::TextureFormat| {
+            context
+                .device
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: None,
+                    layout: Some(layout),
+                    vertex: wgpu::VertexState {
+                        module: shader,
+                        compilation_options: Default::default(),
+                        entry_point: "vs_main",
+                        buffers: &[Vertex::desc()],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: shader,
+                        compilation_options: Default::default(),
+                        entry_point: "fs_main",
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format,
+                            blend: Some(wgpu::BlendState::REPLACE),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        strip_index_format: None,
+                        front_face: wgpu::FrontFace::Ccw,
+                        cull_mode: Some(wgpu::Face::Back),
+                        polygon_mode: wgpu::PolygonMode::Fill,
+                        unclipped_depth: false,
+                        conservative: false,
+                    },
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState {
+                        count: 1,
+                        mask: !0,
+                        alpha_to_coverage_enabled: false,
+                    },
+                    multiview: None,
+                    cache: None,
+                })
+        };
+        let pipeline = create_pipeline(&shader, &render_pipeline_layout, TARGET_FORMAT);
+
+        let mip_shader = context
             .device
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
                 label: None,
-                layout: Some(&render_pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &shader,
-                    compilation_options: Default::default(),
-                    entry_point: "vs_main",
-                    buffers: &[Vertex::desc()],
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: &shader,
-                    compilation_options: Default::default(),
-                    entry_point: "fs_main",
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: surface_config.format,
-                        blend: Some(wgpu::BlendState::REPLACE),
-                        write_mask: wgpu::ColorWrites::ALL,
-                    })],
-                }),
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList,
-                    strip_index_format: None,
-                    front_face: wgpu::FrontFace::Ccw,
-                    cull_mode: Some(wgpu::Face::Back),
-                    polygon_mode: wgpu::PolygonMode::Fill,
-                    unclipped_depth: false,
-                    conservative: false,
-                },
-                depth_stencil: None,
-                multisample: wgpu::MultisampleState {
-                    count: 1,
-                    mask: !0,
-                    alpha_to_coverage_enabled: false,
-                },
-                multiview: None,
-                cache: None,
+                source: wgpu::ShaderSource::Wgsl(include_str!("shaders/downsample.wgsl").into()),
             });
+        let mip_bind_group_layout =
+            context
+                .device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                            count: None,
+                        },
+                    ],
+                    label: Some("mip_source_bind_group_layout"),
+                });
+        let mip_pipeline_layout =
+            context
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: None,
+                    bind_group_layouts: &[&mip_bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+        let mip_pipeline = create_pipeline(&mip_shader, &mip_pipeline_layout, MIP_FORMAT);
 
         let sampler = context.device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
             address_mode_w: wgpu::AddressMode::ClampToEdge,
             mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
             ..Default::default()
         });
 
+        let transform = context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: std::mem::size_of::<Transform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
         Presenter {
             context,
-            surface,
-            surface_config,
+            target,
             pipeline,
             vertex_buffer,
             index_buffer,
             num_indices,
             bind_group_layout,
             sampler,
+            transform,
+            mip_pipeline,
+            mip_bind_group_layout,
+            mip_chain: None,
             bkg_color: wgpu::Color::BLACK,
+            scale_mode: ScaleMode::Fit,
+            filter: ScaleFilter::Bilinear,
+            sample_aspect_ratio: 1.0,
+            orientation: Orientation::default(),
+            crop: None,
+        }
+    }
+
+    pub fn set_scale_mode(&mut self, mode: ScaleMode) {
+        self.scale_mode = mode;
+    }
+
+    pub fn set_filter(&mut self, filter: ScaleFilter) {
+        self.filter = filter;
+    }
+
+    /// Sets the sample aspect ratio of the frames to draw, i.e. the width of
+    /// their pixels relative to their height.
+    pub fn set_sample_aspect_ratio(&mut self, sample_aspect_ratio: f32) {
+        self.sample_aspect_ratio = sample_aspect_ratio;
+    }
+
+    /// Sets the rotation and flips applied to the frames to draw.
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.orientation = orientation;
+    }
+
+    /// Restricts the frames to draw to `crop`, `None` draws them whole.
+    pub fn set_crop(&mut self, crop: Option<CropRect>) {
+        self.crop = crop;
+    }
+
+    /// Draws `texture` into the target. A frame is skipped if the surface
+    /// times out, and the surface is reconfigured if it is outdated or lost.
+    pub fn draw(&mut self, texture: &Texture) -> anyhow::Result<()> {
+        let src_size = texture.texture.size();
+        let crop = clamp_crop(self.crop, (src_size.width, src_size.height));
+        let view_port = calc_view_port(
+            (crop.width, crop.height),
+            self.sample_aspect_ratio,
+            self.orientation,
+            self.target_size(),
+            self.scale_mode,
+        );
+        let use_mips = match &view_port {
+            Some(view_port) => self.prepare(texture, crop, view_port),
+            None => false,
+        };
+        let source = match (use_mips, &self.mip_chain) {
+            (true, Some(mip_chain)) => &mip_chain.view,
+            _ => &texture.view,
+        };
+
+        match &self.target {
+            Target::Surface { surface, config } => {
+                let dst_texture = match surface.get_current_texture() {
+                    Ok(dst_texture) => dst_texture,
+                    Err(wgpu::SurfaceError::Timeout) => return Ok(()),
+                    Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
+                        surface.configure(&self.context.device, config);
+                        surface.get_current_texture()?
+                    }
+                    Err(e) => return Err(e.into()),
+                };
+                let dst_texture_view = dst_texture
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                self.render(source, &dst_texture_view, view_port);
+                dst_texture.present();
+            }
+            Target::Offscreen(dst_texture) => {
+                self.render(source, &dst_texture.view, view_port);
+            }
+        }
+        Ok(())
+    }
+
+    fn target_size(&self) -> (u32, u32) {
+        match &self.target {
+            Target::Surface { config, .. } => (config.width, config.height),
+            Target::Offscreen(texture) => {
+                let size = texture.texture.size();
+                (size.width, size.height)
+            }
+        }
+    }
+
+    /// Writes the transform of `view_port` and, if the frame is downscaled,
+    /// generates its mipmaps. Returns whether the mipmaps are sampled.
+    fn prepare(&mut self, texture: &Texture, crop: CropRect, view_port: &ViewPort) -> bool {
+        let (src_width, src_height) = if self.orientation.is_transposed() {
+            (crop.height, crop.width)
+        } else {
+            (crop.width, crop.height)
+        };
+        // Frame texels per target pixel.
+        let ratio = (view_port.tex_scale[0] * src_width as f32 / view_port.width)
+            .max(view_port.tex_scale[1] * src_height as f32 / view_port.height);
+        // The kernel filters sample the largest level downscaled by less
+        // than 2, the bilinear one lets the sampler blend between levels.
+        let use_mips = match self.filter {
+            ScaleFilter::Bilinear => ratio > 1.0,
+            _ => ratio >= 2.0,
+        };
+        let level = if use_mips {
+            let level_count = self.generate_mipmaps(texture);
+            (ratio.log2() as u32).min(level_count - 1)
+        } else {
+            0
+        };
+
+        let src_size = texture.texture.size();
+        let (width, height) = (src_size.width as f32, src_size.height as f32);
+        let tex_coords = |point| {
+            let [x, y] = oriented_tex_coords(self.orientation, point);
+            [
+                (crop.x as f32 + x * crop.width as f32) / width,
+                (crop.y as f32 + y * crop.height as f32) / height,
+            ]
+        };
+        let origin = tex_coords((0.0, 0.0));
+        let x_axis = tex_coords((1.0, 0.0));
+        let y_axis = tex_coords((0.0, 1.0));
+        let transform = Transform {
+            tex_offset: view_port.tex_offset,
+            tex_scale: view_port.tex_scale,
+            to_texture: [
+                [x_axis[0] - origin[0], x_axis[1] - origin[1]],
+                [y_axis[0] - origin[0], y_axis[1] - origin[1]],
+            ],
+            to_texture_offset: origin,
+            filter_index: self.filter.index(),
+            level,
+            crop_min: [crop.x >> level, crop.y >> level],
+            crop_max: [
+                crop_end(crop.x + crop.width, src_size.width, level),
+                crop_end(crop.y + crop.height, src_size.height, level),
+            ],
+        };
+        self.context
+            .queue
+            .write_buffer(&self.transform, 0, bytemuck::bytes_of(&transform));
+        use_mips
+    }
+
+    /// Renders `texture` and its successive halves into the mip chain,
+    /// which is recreated if its size does not match. The levels are kept
+    /// until the texture or its generation changes, e.g. across redraws of
+    /// the same frame. Returns the number of levels.
+    fn generate_mipmaps(&mut self, texture: &Texture) -> u32 {
+        let size = texture.texture.size();
+        let mut mip_chain = match self.mip_chain.take() {
+            Some(mip_chain) if mip_chain.texture.size() == size => mip_chain,
+            _ => MipChain::new(
+                &self.context,
+                &self.mip_bind_group_layout,
+                &self.sampler,
+                size.width,
+                size.height,
+            ),
+        };
+        let source = Some((texture.texture.global_id(), texture.generation));
+        if mip_chain.source == source {
+            return self.mip_chain.insert(mip_chain).level_count();
+        }
+        mip_chain.source = source;
+
+        let frame_bind_group = new_source_bind_group(
+            &self.context,
+            &self.mip_bind_group_layout,
+            &texture.view,
+            &self.sampler,
+        );
+        let sources = std::iter::once(&frame_bind_group).chain(&mip_chain.level_bind_groups);
+
+        let mut encoder = self
+            .context
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        for (view, bind_group) in mip_chain.level_views.iter().zip(sources) {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            render_pass.set_pipeline(&self.mip_pipeline);
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
         }
+        self.context.queue.submit(Some(encoder.finish()));
+
+        self.mip_chain.insert(mip_chain).level_count()
     }
 
-    pub fn draw(&mut self, texture: &Texture) {
+    /// Reads back what was last drawn offscreen.
+    pub fn read_pixels(&self) -> anyhow::Result<image::RgbaImage> {
+        match &self.target {
+            Target::Surface { .. } => Err(anyhow::anyhow!("Presenter is not offscreen")),
+            Target::Offscreen(texture) => self.context.read_texture(&texture.texture),
+        }
+    }
+
+    fn render(
+        &self,
+        source: &wgpu::TextureView,
+        dst_view: &wgpu::TextureView,
+        view_port: Option<ViewPort>,
+    ) {
         let bind_group = self
             .context
             .device
@@ -235,23 +821,24 @@ impl Presenter {
                 entries: &[
                     wgpu::BindGroupEntry {
                         binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&texture.view),
+                        resource: wgpu::BindingResource::TextureView(source),
                     },
                     wgpu::BindGroupEntry {
                         binding: 1,
                         resource: wgpu::BindingResource::Sampler(&self.sampler),
                     },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: &self.transform,
+                            offset: 0,
+                            size: None,
+                        }),
+                    },
                 ],
                 label: Some("source_texture_bind_group"),
             });
 
-        let dst_texture = self
-            .surface
-            .get_current_texture()
-            .expect("Get current texture failed");
-        let dst_texture_view = dst_texture
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
         let mut encoder = self
             .context
             .device
@@ -261,7 +848,7 @@ impl Presenter {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: None,
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &dst_texture_view,
+                    view: dst_view,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(self.bkg_color),
@@ -273,28 +860,182 @@ impl Presenter {
                 occlusion_query_set: None,
             });
 
-            let src_size = texture.texture.size();
-            let dst_size = dst_texture.texture.size();
-            let view_port = calc_view_port(
-                (src_size.width, src_size.height),
-                (dst_size.width, dst_size.height),
-            );
-
-            render_pass.set_viewport(view_port.0, view_port.1, view_port.2, view_port.3, 0.0, 1.0);
-            render_pass.set_pipeline(&self.pipeline);
-            render_pass.set_bind_group(0, &bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+            // Nothing but the background is visible otherwise.
+            if let Some(view_port) = view_port {
+                render_pass.set_viewport(
+                    view_port.x,
+                    view_port.y,
+                    view_port.width,
+                    view_port.height,
+                    0.0,
+                    1.0,
+                );
+                render_pass.set_pipeline(&self.pipeline);
+                render_pass.set_bind_group(0, &bind_group, &[]);
+                render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+                render_pass
+                    .set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+                render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+            }
         }
         self.context.queue.submit(Some(encoder.finish()));
-        dst_texture.present();
     }
 
-    pub fn resize(&mut self, width: u32, height: u32) {
-        self.surface_config.width = width.max(1);
-        self.surface_config.height = height.max(1);
-        self.surface
-            .configure(&self.context.device, &self.surface_config);
+    pub fn resize(&mut self, width: u32, height: u32) -> anyhow::Result<()> {
+        match &mut self.target {
+            Target::Surface { surface, config } => {
+                config.width = width.max(1);
+                config.height = height.max(1);
+                surface.configure(&self.context.device, config);
+            }
+            Target::Offscreen(texture) => {
+                *texture = new_offscreen_texture(&self.context, width, height)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Texture coordinates of the top-left, top-right, bottom-left and
+    /// bottom-right corners of the displayed frame.
+    fn corners(orientation: Orientation) -> [[f32; 2]; 4] {
+        [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)]
+            .map(|point| oriented_tex_coords(orientation, point))
+    }
+
+    const TOP_LEFT: [f32; 2] = [0.0, 0.0];
+    const TOP_RIGHT: [f32; 2] = [1.0, 0.0];
+    const BOTTOM_LEFT: [f32; 2] = [0.0, 1.0];
+    const BOTTOM_RIGHT: [f32; 2] = [1.0, 1.0];
+
+    /// A viewport showing the whole frame.
+    fn whole(x: f32, y: f32, width: f32, height: f32) -> Option<ViewPort> {
+        Some(ViewPort {
+            x,
+            y,
+            width,
+            height,
+            tex_offset: [0.0, 0.0],
+            tex_scale: [1.0, 1.0],
+        })
+    }
+
+    // An anamorphic frame, displayed at 200x50, in a square target.
+    const SRC_SIZE: (u32, u32) = (100, 50);
+    const SAR: f32 = 2.0;
+    const DST_SIZE: (u32, u32) = (400, 400);
+    const ZOOM: ScaleMode = ScaleMode::Zoom {
+        factor: 2.0,
+        pan: (10.0, -20.0),
+    };
+
+    #[test]
+    fn view_ports() {
+        let view_port =
+            |mode| calc_view_port(SRC_SIZE, SAR, Orientation::default(), DST_SIZE, mode);
+        assert_eq!(view_port(ScaleMode::Fit), whole(0.0, 150.0, 400.0, 100.0));
+        // Displayed at 1600x400, of which the middle quarter is visible.
+        assert_eq!(
+            view_port(ScaleMode::Fill),
+            Some(ViewPort {
+                x: 0.0,
+                y: 0.0,
+                width: 400.0,
+                height: 400.0,
+                tex_offset: [0.375, 0.0],
+                tex_scale: [0.25, 1.0],
+            })
+        );
+        assert_eq!(view_port(ScaleMode::Stretch), whole(0.0, 0.0, 400.0, 400.0));
+        assert_eq!(
+            view_port(ScaleMode::Native),
+            whole(150.0, 175.0, 100.0, 50.0)
+        );
+        assert_eq!(view_port(ZOOM), whole(110.0, 130.0, 200.0, 100.0));
+    }
+
+    #[test]
+    fn transposed_view_ports() {
+        // Displayed at 50x200 once rotated, at 50x100 natively.
+        let orientation = Orientation::new(90, false, false);
+        let view_port = |mode| calc_view_port(SRC_SIZE, SAR, orientation, DST_SIZE, mode);
+        assert_eq!(view_port(ScaleMode::Fit), whole(150.0, 0.0, 100.0, 400.0));
+        assert_eq!(
+            view_port(ScaleMode::Fill),
+            Some(ViewPort {
+                x: 0.0,
+                y: 0.0,
+                width: 400.0,
+                height: 400.0,
+                tex_offset: [0.0, 0.375],
+                tex_scale: [1.0, 0.25],
+            })
+        );
+        assert_eq!(view_port(ScaleMode::Stretch), whole(0.0, 0.0, 400.0, 400.0));
+        assert_eq!(
+            view_port(ScaleMode::Native),
+            whole(175.0, 150.0, 50.0, 100.0)
+        );
+        assert_eq!(view_port(ZOOM), whole(160.0, 80.0, 100.0, 200.0));
+    }
+
+    #[test]
+    fn view_port_outside_target() {
+        let mode = ScaleMode::Zoom {
+            factor: 1.0,
+            pan: (1000.0, 0.0),
+        };
+        assert_eq!(
+            calc_view_port(SRC_SIZE, SAR, Orientation::default(), DST_SIZE, mode),
+            None
+        );
+    }
+
+    #[test]
+    fn oriented_corners() {
+        let cases = [
+            (
+                Orientation::default(),
+                [TOP_LEFT, TOP_RIGHT, BOTTOM_LEFT, BOTTOM_RIGHT],
+            ),
+            // Clockwise, the left column of the texture goes to the top.
+            (
+                Orientation::new(90, false, false),
+                [BOTTOM_LEFT, TOP_LEFT, BOTTOM_RIGHT, TOP_RIGHT],
+            ),
+            (
+                Orientation::new(180, false, false),
+                [BOTTOM_RIGHT, BOTTOM_LEFT, TOP_RIGHT, TOP_LEFT],
+            ),
+            (
+                Orientation::new(270, false, false),
+                [TOP_RIGHT, BOTTOM_RIGHT, TOP_LEFT, BOTTOM_LEFT],
+            ),
+            (
+                Orientation::new(0, true, false),
+                [TOP_RIGHT, TOP_LEFT, BOTTOM_RIGHT, BOTTOM_LEFT],
+            ),
+            (
+                Orientation::new(0, false, true),
+                [BOTTOM_LEFT, BOTTOM_RIGHT, TOP_LEFT, TOP_RIGHT],
+            ),
+            // Transpose, about the main diagonal.
+            (
+                Orientation::new(90, false, true),
+                [TOP_LEFT, BOTTOM_LEFT, TOP_RIGHT, BOTTOM_RIGHT],
+            ),
+            // Anti-transpose, about the other diagonal.
+            (
+                Orientation::new(270, false, true),
+                [BOTTOM_RIGHT, TOP_RIGHT, BOTTOM_LEFT, TOP_LEFT],
+            ),
+        ];
+        for (orientation, expected) in cases {
+            assert_eq!(corners(orientation), expected, "{:?}", orientation);
+        }
     }
 }
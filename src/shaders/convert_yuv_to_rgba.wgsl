@@ -66,6 +66,22 @@ fn vs_main(
     return out;
 }
 
+// Brings the sampled values to the 8-bit scale of the matrices above.
+struct Params {
+    // Visible area of the planes, in texture coordinates.
+    crop_offset: vec2f,
+    crop_scale: vec2f,
+    matrix_index: u32,
+    // 0: planar, 1: interleaved UV in t_u, 2: interleaved VU in t_u.
+    chroma_layout: u32,
+    scale: f32,
+    y_offset: f32,
+    uv_offset: f32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+};
+
 @group(0) @binding(0)
 var t_y: texture_2d<f32>;
 @group(0) @binding(1)
@@ -75,35 +91,47 @@ var t_v: texture_2d<f32>;
 @group(0) @binding(3)
 var s_yuv: sampler;
 @group(0) @binding(4)
-var<uniform> matrix_index: u32;
+var<uniform> params: Params;
 
-@fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4f {
-    var y = textureSample(t_y, s_yuv, in.tex_coords).r;
-    let u = textureSample(t_u, s_yuv, in.tex_coords).r - 128.0 / 255.0;
-    let v = textureSample(t_v, s_yuv, in.tex_coords).r - 128.0 / 255.0;
+// Explicit level sampling, so that the conversion is shared by the fragment
+// and the compute entry points.
+fn yuv_to_rgb(output_coords: vec2f) -> vec3f {
+    let tex_coords = params.crop_offset + output_coords * params.crop_scale;
+    let y = textureSampleLevel(t_y, s_yuv, tex_coords, 0.0).r * params.scale - params.y_offset;
+    let u_sample = textureSampleLevel(t_u, s_yuv, tex_coords, 0.0);
+    let v_sample = textureSampleLevel(t_v, s_yuv, tex_coords, 0.0);
+
+    var uv: vec2f;
+    if (params.chroma_layout == 1u) {
+        uv = u_sample.rg;
+    } else if (params.chroma_layout == 2u) {
+        uv = u_sample.gr;
+    } else {
+        uv = vec2f(u_sample.r, v_sample.r);
+    }
+    uv = uv * params.scale - params.uv_offset;
 
     var matrix: mat3x3f;
-    if (matrix_index == 0u) {
-        y -= 16.0 / 255.0;
+    if (params.matrix_index == 0u) {
         matrix = bt601_limited_yuv_to_rgb;
-    } else if (matrix_index == 1u) {
+    } else if (params.matrix_index == 1u) {
         matrix = bt601_full_yuv_to_rgb;
-    } else if (matrix_index == 2u) {
-        y -= 16.0 / 255.0;
+    } else if (params.matrix_index == 2u) {
         matrix = bt709_limited_yuv_to_rgb;
-    } else if (matrix_index == 3u) {
+    } else if (params.matrix_index == 3u) {
         matrix = bt709_full_yuv_to_rgb;
-    } else if (matrix_index == 4u) {
-        y -= 16.0 / 255.0;
+    } else if (params.matrix_index == 4u) {
         matrix = bt2020_limited_yuv_to_rgb;
-    } else if (matrix_index == 5u) {
+    } else if (params.matrix_index == 5u) {
         matrix = bt2020_full_yuv_to_rgb;
     } else {
-        y -= 16.0 / 255.0;
         matrix = bt709_limited_yuv_to_rgb;
     }
 
-    let rgb = matrix * vec3f(y, u, v);
-    return vec4f(rgb, 1.0);
-}
\ No newline at end of file
+    return matrix * vec3f(y, uv);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    return vec4f(yuv_to_rgb(in.tex_coords), 1.0);
+}
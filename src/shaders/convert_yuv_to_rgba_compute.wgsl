@@ -0,0 +1,17 @@
+// Compute entry points, compiled appended to convert_yuv_to_rgba.wgsl.
+
+// Storage textures must declare their format, hence one output and entry
+// point per output format, see convert_yuv_to_rgba_compute_16.wgsl. The
+// frame is resampled to the output size.
+@group(1) @binding(0)
+var out_rgba8: texture_storage_2d<rgba8unorm, write>;
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3u) {
+    let size = textureDimensions(out_rgba8);
+    if (id.x >= size.x || id.y >= size.y) {
+        return;
+    }
+    let tex_coords = (vec2f(id.xy) + 0.5) / vec2f(size);
+    textureStore(out_rgba8, id.xy, vec4f(yuv_to_rgb(tex_coords), 1.0));
+}
@@ -0,0 +1,15 @@
+// Compute entry point for high bit depth frames, compiled appended to
+// convert_yuv_to_rgba_compute.wgsl on devices with 16-bit normalized formats.
+
+@group(1) @binding(1)
+var out_rgba16: texture_storage_2d<rgba16unorm, write>;
+
+@compute @workgroup_size(8, 8)
+fn cs_main_16(@builtin(global_invocation_id) id: vec3u) {
+    let size = textureDimensions(out_rgba16);
+    if (id.x >= size.x || id.y >= size.y) {
+        return;
+    }
+    let tex_coords = (vec2f(id.xy) + 0.5) / vec2f(size);
+    textureStore(out_rgba16, id.xy, vec4f(yuv_to_rgb(tex_coords), 1.0));
+}
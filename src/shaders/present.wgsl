@@ -10,12 +10,33 @@ struct VertexOutput {
     @location(0) tex_coords: vec2f,
 };
 
+// Maps the quad to the part of the displayed frame visible in the viewport,
+// then to the texture through the rotation, flips and crop of the frame.
+struct Transform {
+    tex_offset: vec2f,
+    tex_scale: vec2f,
+    to_texture: mat2x2f,
+    to_texture_offset: vec2f,
+    // 0: bilinear, 1: bicubic, 2: Lanczos-2, 3: Lanczos-3.
+    filter_index: u32,
+    // Mip level the kernel filters sample.
+    level: u32,
+    // First and last texels of the crop in that level, the taps of the
+    // kernel filters are clamped to them.
+    crop_min: vec2u,
+    crop_max: vec2u,
+};
+
+@group(0) @binding(2)
+var<uniform> transform: Transform;
+
 @vertex
 fn vs_main(
     in: VertexInput,
 ) -> VertexOutput {
     var out: VertexOutput;
-    out.tex_coords = in.tex_coords;
+    let display_coords = transform.tex_offset + in.tex_coords * transform.tex_scale;
+    out.tex_coords = transform.to_texture * display_coords + transform.to_texture_offset;
     out.clip_position = vec4f(in.position, 1.0);
     return out;
 }
@@ -26,7 +47,80 @@ var t_source: texture_2d<f32>;
 @group(0) @binding(1)
 var s_source: sampler;
 
+const PI = 3.14159265;
+
+// Catmull-Rom spline.
+fn bicubic(x: f32) -> f32 {
+    let ax = abs(x);
+    if (ax < 1.0) {
+        return (1.5 * ax - 2.5) * ax * ax + 1.0;
+    } else if (ax < 2.0) {
+        return ((-0.5 * ax + 2.5) * ax - 4.0) * ax + 2.0;
+    }
+    return 0.0;
+}
+
+fn lanczos(x: f32, a: f32) -> f32 {
+    if (abs(x) < 1e-5) {
+        return 1.0;
+    } else if (abs(x) >= a) {
+        return 0.0;
+    }
+    let px = PI * x;
+    return a * sin(px) * sin(px / a) / (px * px);
+}
+
+fn kernel_weight(x: f32) -> f32 {
+    switch (transform.filter_index) {
+        case 1u: {
+            return bicubic(x);
+        }
+        case 2u: {
+            return lanczos(x, 2.0);
+        }
+        default: {
+            return lanczos(x, 3.0);
+        }
+    }
+}
+
+// Separable kernel over the (2 * radius)^2 texels around `tex_coords`.
+fn sample_kernel(tex_coords: vec2f, radius: i32) -> vec4f {
+    let level = i32(transform.level);
+    let size = vec2i(textureDimensions(t_source, level));
+    let position = tex_coords * vec2f(size) - 0.5;
+    let base = vec2i(floor(position));
+    let fraction = position - floor(position);
+
+    var sum = vec4f(0.0);
+    var weight_sum = 0.0;
+    for (var j = 1 - radius; j <= radius; j++) {
+        let weight_y = kernel_weight(f32(j) - fraction.y);
+        for (var i = 1 - radius; i <= radius; i++) {
+            let weight = kernel_weight(f32(i) - fraction.x) * weight_y;
+            let texel = clamp(
+                base + vec2i(i, j),
+                vec2i(transform.crop_min),
+                vec2i(transform.crop_max),
+            );
+            sum += textureLoad(t_source, texel, level) * weight;
+            weight_sum += weight;
+        }
+    }
+    return sum / weight_sum;
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4f {
-    return textureSample(t_source, s_source, in.tex_coords);
+    switch (transform.filter_index) {
+        case 0u: {
+            return textureSample(t_source, s_source, in.tex_coords);
+        }
+        case 3u: {
+            return sample_kernel(in.tex_coords, 3);
+        }
+        default: {
+            return sample_kernel(in.tex_coords, 2);
+        }
+    }
 }
\ No newline at end of file
@@ -3,6 +3,9 @@ use anyhow::*;
 pub(crate) struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
+    /// Incremented whenever the content of the texture is replaced, e.g. by
+    /// a new frame, so that what is derived from it can be kept until then.
+    pub generation: u64,
 }
 
 impl Texture {
@@ -30,20 +33,26 @@ impl Texture {
         });
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
 
-        Ok(Self { texture, view })
+        Ok(Self {
+            texture,
+            view,
+            generation: 0,
+        })
     }
 
-    pub fn new_rgba(device: &wgpu::Device, width: u32, height: u32) -> Result<Self> {
-        let usage = wgpu::TextureUsages::TEXTURE_BINDING
-            | wgpu::TextureUsages::COPY_DST
-            | wgpu::TextureUsages::RENDER_ATTACHMENT;
-        Self::new(
-            device,
-            wgpu::TextureFormat::Rgba8Unorm,
-            usage,
-            width,
-            height,
-        )
+    /// A texture holding a frame to present, in `Rgba8Unorm` or, for high
+    /// bit depth frames, `Rgba16Float`. `output_usage` is what the converter
+    /// writing it needs.
+    pub fn new_frame(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        output_usage: wgpu::TextureUsages,
+        width: u32,
+        height: u32,
+    ) -> Result<Self> {
+        let usage =
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | output_usage;
+        Self::new(device, format, usage, width, height)
     }
 
     pub fn new_src(
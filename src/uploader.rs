@@ -0,0 +1,263 @@
+use anyhow::{anyhow, bail, Result};
+use std::{
+    rc::Rc,
+    sync::{mpsc, Arc},
+};
+
+use crate::wgpu_context::WgpuContext;
+
+/// A plane of a decoded frame, copied as a whole into `texture`.
+pub(crate) struct Plane<'a> {
+    pub texture: &'a wgpu::Texture,
+    pub data: &'a [u8],
+    /// Bytes between the starts of two rows in `data`.
+    pub pitch: usize,
+}
+
+/// Uploads frames through a ring of staging buffers which stay mapped while
+/// the GPU does not use them. The planes are written straight into the
+/// buffers with the row alignment of `copy_buffer_to_texture`, instead of
+/// being staged once more by `Queue::write_texture`. Frames converted on the
+/// CPU can be written into them directly with `upload_with`.
+///
+/// Call `upload` for the planes of a frame, submit the encoder, then call
+/// `recall` so that the buffers are reused once the copies are done. Once
+/// `MAX_BUFFERS` buffers exist, uploads wait for the GPU to release one.
+pub(crate) struct Uploader {
+    context: Rc<WgpuContext>,
+    // Mapped buffers, ready to be written.
+    free: Vec<Arc<wgpu::Buffer>>,
+    // Unmapped buffers, copied from by the commands being recorded.
+    in_flight: Vec<Arc<wgpu::Buffer>>,
+    // Number of buffers, free, in flight or being mapped again.
+    count: usize,
+    // Buffers come back here once mapped again, `None` for those that
+    // failed to map.
+    sender: mpsc::Sender<Option<Arc<wgpu::Buffer>>>,
+    receiver: mpsc::Receiver<Option<Arc<wgpu::Buffer>>>,
+}
+
+/// Maximum number of staging buffers of an `Uploader`.
+const MAX_BUFFERS: usize = 4;
+
+impl Uploader {
+    pub fn new(context: Rc<WgpuContext>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Uploader {
+            context,
+            free: Vec::new(),
+            in_flight: Vec::new(),
+            count: 0,
+            sender,
+            receiver,
+        }
+    }
+
+    /// Writes `planes` into a staging buffer and records their copies to
+    /// their textures in `encoder`. Fails if a plane holds less data than
+    /// its texture.
+    pub fn upload(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        planes: &[Plane<'_>],
+    ) -> Result<()> {
+        // Offset, row size and aligned row size of each plane in the buffer.
+        let mut layouts = Vec::with_capacity(planes.len());
+        let mut size = 0;
+        for plane in planes {
+            let texture_size = plane.texture.size();
+            let row_size = (texture_size.width * block_size(plane.texture)?) as usize;
+            let height = texture_size.height as usize;
+            if plane.pitch < row_size
+                || plane.data.len() < plane.pitch * height.saturating_sub(1) + row_size
+            {
+                bail!(
+                    "Plane of {} bytes with a pitch of {} is too small for a {}x{} texture",
+                    plane.data.len(),
+                    plane.pitch,
+                    texture_size.width,
+                    texture_size.height
+                );
+            }
+
+            let aligned_row_size =
+                row_size.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as _);
+            layouts.push((size, row_size, aligned_row_size));
+            size += aligned_row_size * height;
+        }
+
+        let buffer = self.buffer(size as u64);
+        {
+            let mut mapped = buffer.slice(..size as u64).get_mapped_range_mut();
+            for (plane, &(offset, row_size, aligned_row_size)) in planes.iter().zip(&layouts) {
+                let height = plane.texture.size().height as usize;
+                let dst = &mut mapped[offset..offset + aligned_row_size * height];
+                if plane.pitch == aligned_row_size {
+                    let len = plane.pitch * (height - 1) + row_size;
+                    dst[..len].copy_from_slice(&plane.data[..len]);
+                } else {
+                    for (dst_row, src_row) in dst
+                        .chunks_mut(aligned_row_size)
+                        .zip(plane.data.chunks(plane.pitch))
+                    {
+                        dst_row[..row_size].copy_from_slice(&src_row[..row_size]);
+                    }
+                }
+            }
+        }
+        buffer.unmap();
+
+        for (plane, &(offset, _, aligned_row_size)) in planes.iter().zip(&layouts) {
+            copy_to_texture(encoder, &buffer, offset, aligned_row_size, plane.texture);
+        }
+        self.in_flight.push(buffer);
+        Ok(())
+    }
+
+    /// Lets `write` produce an image of `height` rows of `row_size` bytes
+    /// straight into a staging buffer, e.g. from a CPU conversion, and
+    /// records the copy of its area at `origin`, of the size of `texture`, to
+    /// `texture`. `write` gets the rows and the bytes between their starts.
+    pub fn upload_with(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        row_size: usize,
+        height: usize,
+        origin: (u32, u32),
+        write: impl FnOnce(&mut [u8], usize) -> Result<()>,
+    ) -> Result<()> {
+        let texture_size = texture.size();
+        let block_size = block_size(texture)? as usize;
+        if (origin.0 + texture_size.width) as usize * block_size > row_size
+            || (origin.1 + texture_size.height) as usize > height
+        {
+            bail!(
+                "Image of {} bytes by {} rows is too small for a {}x{} texture at {:?}",
+                row_size,
+                height,
+                texture_size.width,
+                texture_size.height,
+                origin
+            );
+        }
+
+        let pitch = row_size.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as _);
+        let size = (pitch * height) as u64;
+        let buffer = self.buffer(size);
+        let result = write(&mut buffer.slice(..size).get_mapped_range_mut(), pitch);
+        buffer.unmap();
+        if result.is_ok() {
+            let offset = origin.1 as usize * pitch + origin.0 as usize * block_size;
+            copy_to_texture(encoder, &buffer, offset, pitch, texture);
+        }
+        // Mapped again by the next `recall` in any case.
+        self.in_flight.push(buffer);
+        result
+    }
+
+    /// Maps the buffers of the uploads again, to reuse them once the GPU is
+    /// done with them. Must be called after the encoders given to `upload`
+    /// are submitted.
+    pub fn recall(&mut self) {
+        for buffer in self.in_flight.drain(..) {
+            let sender = self.sender.clone();
+            buffer
+                .clone()
+                .slice(..)
+                .map_async(wgpu::MapMode::Write, move |result| {
+                    let _ = sender.send(result.is_ok().then_some(buffer));
+                });
+        }
+    }
+
+    /// Returns a mapped buffer of at least `size` bytes, the smallest free
+    /// one if any. Waits for a buffer to be released rather than creating
+    /// more than `MAX_BUFFERS`.
+    fn buffer(&mut self, size: u64) -> Arc<wgpu::Buffer> {
+        self.context.device.poll(wgpu::Maintain::Poll);
+        self.receive();
+        loop {
+            let best_fit = (0..self.free.len())
+                .filter(|&i| self.free[i].size() >= size)
+                .min_by_key(|&i| self.free[i].size());
+            if let Some(i) = best_fit {
+                return self.free.swap_remove(i);
+            }
+            if self.count < MAX_BUFFERS {
+                break;
+            }
+            // All the free buffers are too small, the smallest is replaced.
+            if let Some(i) = (0..self.free.len()).min_by_key(|&i| self.free[i].size()) {
+                self.free.swap_remove(i);
+                self.count -= 1;
+                break;
+            }
+            // Buffers not recalled yet can not be waited for.
+            if self.count == self.in_flight.len() {
+                break;
+            }
+            self.context.device.poll(wgpu::Maintain::Wait);
+            if self.receive() == 0 {
+                break;
+            }
+        }
+
+        self.count += 1;
+        Arc::new(self.context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("upload_buffer"),
+            size,
+            usage: wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: true,
+        }))
+    }
+
+    /// Takes the buffers mapped again back, returns how many came back.
+    fn receive(&mut self) -> usize {
+        let mut received = 0;
+        for buffer in self.receiver.try_iter() {
+            received += 1;
+            match buffer {
+                Some(buffer) => self.free.push(buffer),
+                None => self.count -= 1,
+            }
+        }
+        received
+    }
+}
+
+fn block_size(texture: &wgpu::Texture) -> Result<u32> {
+    texture
+        .format()
+        .block_copy_size(None)
+        .ok_or_else(|| anyhow!("Can not upload to {:?} textures", texture.format()))
+}
+
+/// Records the copy of the rows at `offset` in `buffer`, `pitch` bytes apart,
+/// to the whole of `texture`.
+fn copy_to_texture(
+    encoder: &mut wgpu::CommandEncoder,
+    buffer: &wgpu::Buffer,
+    offset: usize,
+    pitch: usize,
+    texture: &wgpu::Texture,
+) {
+    let texture_size = texture.size();
+    encoder.copy_buffer_to_texture(
+        wgpu::ImageCopyBuffer {
+            buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: offset as u64,
+                bytes_per_row: Some(pitch as u32),
+                rows_per_image: Some(texture_size.height),
+            },
+        },
+        wgpu::ImageCopyTexture {
+            aspect: wgpu::TextureAspect::All,
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        texture_size,
+    );
+}
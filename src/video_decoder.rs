@@ -0,0 +1,93 @@
+extern crate ffmpeg_next as ffmpeg;
+
+pub use crate::ffmpeg::visible_rect;
+use crate::{demuxer::DemuxerStream, MediaError, MediaType};
+
+/// Decodes the packets of a video `DemuxerStream` into frames.
+///
+/// The PTS of each returned frame is the best effort timestamp in the time
+/// base of the stream.
+///
+/// The frames are not cropped: they hold the whole coded picture, including
+/// the padding some codecs add, e.g. the bottom 8 rows of a 1080p H.264
+/// stream coded as 1088 rows. Their `crop_*` fields tell the visible area,
+/// see `visible_rect`. `VideoRenderer` and `OffscreenRenderer` present only
+/// that area.
+pub struct VideoDecoder {
+    stream: DemuxerStream,
+    decoder: ffmpeg::decoder::Video,
+    time_base: ffmpeg::Rational,
+    state: crate::ffmpeg::DecodeState,
+}
+
+impl VideoDecoder {
+    pub fn new(stream: DemuxerStream) -> Result<Self, MediaError> {
+        if stream.media_type() != MediaType::Video {
+            return Err(MediaError::UnsupportedFormat);
+        }
+
+        let time_base = stream.time_base();
+        let context = unsafe { crate::ffmpeg::new_codec_context(stream.as_ptr())? };
+        let mut decoder = context.decoder();
+        decoder.set_packet_time_base(time_base);
+        // The renderers apply the crop of the frames themselves, without
+        // this the decoder applies what it can, down to the alignment of the
+        // data, and clears the fields.
+        unsafe { (*decoder.as_mut_ptr()).apply_cropping = 0 };
+        let decoder = decoder.video()?;
+
+        Ok(VideoDecoder {
+            stream,
+            decoder,
+            time_base,
+            state: Default::default(),
+        })
+    }
+
+    pub fn stream(&self) -> &DemuxerStream {
+        &self.stream
+    }
+
+    pub fn time_base(&self) -> ffmpeg::Rational {
+        self.time_base
+    }
+
+    /// Coded width of the frames, see `visible_rect` for the visible one.
+    pub fn width(&self) -> u32 {
+        self.decoder.width()
+    }
+
+    /// Coded height of the frames.
+    pub fn height(&self) -> u32 {
+        self.decoder.height()
+    }
+
+    pub fn format(&self) -> ffmpeg::format::Pixel {
+        self.decoder.format()
+    }
+
+    /// Returns the next decoded frame, or `None` once the stream has ended and
+    /// all the frames buffered in the decoder have been returned. The frame
+    /// is not cropped, its visible area is given by `visible_rect`.
+    pub fn decode(&mut self) -> Result<Option<ffmpeg::frame::Video>, MediaError> {
+        let mut frame = ffmpeg::frame::Video::empty();
+        if crate::ffmpeg::receive_frame(
+            &mut self.decoder,
+            &self.stream,
+            &mut frame,
+            &mut self.state,
+        )? {
+            let pts = frame.timestamp();
+            frame.set_pts(pts);
+            Ok(Some(frame))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Discards the frames buffered in the decoder, e.g. after a seek.
+    pub fn flush(&mut self) {
+        self.decoder.flush();
+        self.state = Default::default();
+    }
+}
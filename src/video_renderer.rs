@@ -1,5 +1,7 @@
 use std::{
+    ffi::c_int,
     mem::swap,
+    ptr,
     rc::Rc,
     sync::{mpsc, Arc},
 };
@@ -8,15 +10,27 @@ use winit::{dpi::PhysicalSize, window::Window};
 extern crate ffmpeg_next as ffmpeg;
 use ffmpeg::software::scaling;
 
+use crate::clock::{Clock, FrameScheduler};
+pub use crate::convert_from_yuv::ConversionMethod;
+pub use crate::presenter::{CropRect, ScaleFilter, ScaleMode};
+use crate::Orientation;
 use crate::{convert_from_yuv::YuvToRgbaConverter, wgpu_context::WgpuContext};
-use crate::{presenter::Presenter, texture::Texture};
+use crate::{presenter::Presenter, texture::Texture, uploader::Plane, uploader::Uploader};
 type VideoReceiver = mpsc::Receiver<ffmpeg::frame::Video>;
 type VideoSender = mpsc::SyncSender<ffmpeg::frame::Video>;
 
 pub struct VideoRenderer {
     video_receiver: VideoReceiver,
+    scheduler: Option<FrameScheduler>,
     context: Option<Rc<WgpuContext>>,
     presenter: Option<Presenter>,
+    converter: Option<YuvToRgbaConverter>,
+    conversion_method: ConversionMethod,
+    scale_mode: ScaleMode,
+    scale_filter: ScaleFilter,
+    orientation: Orientation,
+    crop: Option<CropRect>,
+    uploader: Option<Uploader>,
     texture: Option<Texture>,
 }
 
@@ -24,138 +38,369 @@ impl VideoRenderer {
     pub fn new(video_receiver: VideoReceiver) -> Self {
         VideoRenderer {
             video_receiver,
+            scheduler: None,
             context: None,
             presenter: None,
+            converter: None,
+            conversion_method: ConversionMethod::Auto,
+            scale_mode: ScaleMode::Fit,
+            scale_filter: ScaleFilter::Bilinear,
+            orientation: Orientation::default(),
+            crop: None,
+            uploader: None,
             texture: None,
         }
     }
 
-    pub fn init(&mut self, window: Arc<Window>, request_redraw: Box<dyn Fn() + Send>) {
+    /// Selects how YUV frames are converted to RGBA on the GPU.
+    pub fn set_conversion_method(&mut self, method: ConversionMethod) {
+        self.conversion_method = method;
+        if let Some(converter) = self.converter.as_mut() {
+            converter.set_method(method);
+        }
+    }
+
+    /// Selects how frames are scaled into the window. Takes effect on the
+    /// next `render`.
+    pub fn set_scale_mode(&mut self, mode: ScaleMode) {
+        self.scale_mode = mode;
+        if let Some(presenter) = self.presenter.as_mut() {
+            presenter.set_scale_mode(mode);
+        }
+    }
+
+    /// Selects the filter frames are scaled with.
+    pub fn set_scale_filter(&mut self, filter: ScaleFilter) {
+        self.scale_filter = filter;
+        if let Some(presenter) = self.presenter.as_mut() {
+            presenter.set_filter(filter);
+        }
+    }
+
+    /// Sets the rotation and flips applied to the frames, e.g. the
+    /// orientation of the video stream.
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.orientation = orientation;
+        if let Some(presenter) = self.presenter.as_mut() {
+            presenter.set_orientation(orientation);
+        }
+    }
+
+    /// Restricts the frames to `crop`, in pixels of their visible area.
+    /// `None` presents them whole.
+    pub fn set_crop(&mut self, crop: Option<CropRect>) {
+        self.crop = crop;
+        if let Some(presenter) = self.presenter.as_mut() {
+            presenter.set_crop(crop);
+        }
+    }
+
+    /// Presents the frames according to their PTS, expressed in `time_base`,
+    /// on `clock`. Without a clock frames are presented as soon as they are
+    /// received. Must be called before `init`.
+    pub fn set_clock(&mut self, clock: Clock, time_base: ffmpeg::Rational) {
+        self.scheduler = Some(FrameScheduler::new(clock, time_base));
+    }
+
+    pub fn init(
+        &mut self,
+        window: Arc<Window>,
+        request_redraw: Box<dyn Fn() + Send>,
+    ) -> anyhow::Result<()> {
         let instance = wgpu::Instance::default();
         println!("WGPU instance created.");
-        let surface = instance.create_surface(window.clone()).unwrap();
-        let context = Rc::new(WgpuContext::new(&instance, &surface));
+        let surface = instance.create_surface(window.clone())?;
+        let context = Rc::new(WgpuContext::new(&instance, &surface)?);
         self.context = Some(context.clone());
+        let mut converter = YuvToRgbaConverter::new(context.clone());
+        converter.set_method(self.conversion_method);
+        self.converter = Some(converter);
+        self.uploader = Some(Uploader::new(context.clone()));
 
         let size = window.inner_size();
-        self.presenter = Some(Presenter::new(
-            context.clone(),
-            surface,
-            size.width,
-            size.height,
-        ));
+        let mut presenter = Presenter::new(context.clone(), surface, size.width, size.height)?;
+        presenter.set_scale_mode(self.scale_mode);
+        presenter.set_filter(self.scale_filter);
+        presenter.set_orientation(self.orientation);
+        presenter.set_crop(self.crop);
+        self.presenter = Some(presenter);
 
         let (sender, mut receiver) = mpsc::sync_channel(1);
         swap(&mut self.video_receiver, &mut receiver);
 
+        let scheduler = self.scheduler.take();
         std::thread::spawn(move || {
-            Self::thread_loop(receiver, sender, request_redraw);
+            Self::thread_loop(receiver, sender, scheduler, request_redraw);
         });
+        Ok(())
     }
 
     pub fn render(&mut self) {
-        if let (Some(context), Some(presenter)) = (self.context.as_ref(), self.presenter.as_mut()) {
-            if let Some(frame) = self.video_receiver.try_recv().ok() {
-                if !Self::is_supported_format(frame.format()) {
-                    panic!("Unsupported format: {:?}", frame.format());
-                }
-
-                self.texture = Self::update_texture(
-                    context,
-                    self.texture.take(),
-                    frame.width(),
-                    frame.height(),
-                );
-
-                if let Some(texture) = self.texture.as_ref() {
-                    match frame.format() {
-                        ffmpeg::format::Pixel::RGBA => {
-                            context.write_texture(
-                                &texture.texture,
-                                frame.data(0),
-                                frame.stride(0) as u32,
-                                frame.width(),
-                                frame.height(),
-                            );
-                        }
-                        _ => {
-                            let converter = YuvToRgbaConverter::new(context.clone());
-                            converter.convert(&frame, texture);
-                        }
+        if let (Some(context), Some(presenter), Some(converter), Some(uploader)) = (
+            self.context.as_ref(),
+            self.presenter.as_mut(),
+            self.converter.as_mut(),
+            self.uploader.as_mut(),
+        ) {
+            if let Ok(frame) = self.video_receiver.try_recv() {
+                match upload_frame(context, converter, uploader, self.texture.take(), &frame) {
+                    Ok(texture) => {
+                        self.texture = Some(texture);
+                        presenter.set_sample_aspect_ratio(sample_aspect_ratio(&frame));
                     }
+                    Err(e) => log::warn!("Failed to upload frame: {}", e),
                 }
             }
 
             if let Some(texture) = self.texture.as_ref() {
-                presenter.draw(&texture);
+                if let Err(e) = presenter.draw(texture) {
+                    log::warn!("Failed to draw frame: {}", e);
+                }
             }
         }
     }
 
     pub fn resize(&mut self, size: PhysicalSize<u32>) {
-        if let Some(context) = self.presenter.as_mut() {
-            context.resize(size.width, size.height);
+        if let Some(presenter) = self.presenter.as_mut() {
+            if let Err(e) = presenter.resize(size.width, size.height) {
+                log::warn!("Failed to resize: {}", e);
+            }
         }
     }
 
-    fn is_supported_format(format: ffmpeg::format::Pixel) -> bool {
-        match format {
-            ffmpeg::format::Pixel::RGBA => true,
-            _ => YuvToRgbaConverter::is_supported_format(format),
+    fn thread_loop(
+        receiver: VideoReceiver,
+        sender: VideoSender,
+        scheduler: Option<FrameScheduler>,
+        request_redraw: Box<dyn Fn() + Send>,
+    ) {
+        while let Ok(frame) = receiver.recv() {
+            if let Some(scheduler) = scheduler.as_ref() {
+                if !scheduler.wait_until_due(frame.pts()) {
+                    continue;
+                }
+            }
+            if sender.send(frame).is_err() {
+                break;
+            }
+            request_redraw();
         }
     }
+}
 
-    fn update_texture(
-        context: &WgpuContext,
-        mut texture: Option<Texture>,
-        width: u32,
-        height: u32,
-    ) -> Option<Texture> {
-        let mut need_reset = false;
-        if let Some(texture) = &texture {
-            let tex_size = texture.texture.size();
-            if (width, height) != (tex_size.width, tex_size.height) {
-                need_reset = true;
-            }
-        }
+/// Renders frames without a window, e.g. to make thumbnails, the same way
+/// `VideoRenderer` presents them.
+pub struct OffscreenRenderer {
+    context: Rc<WgpuContext>,
+    presenter: Presenter,
+    converter: YuvToRgbaConverter,
+    uploader: Uploader,
+    texture: Option<Texture>,
+}
+
+impl OffscreenRenderer {
+    pub fn new(context: Rc<WgpuContext>, width: u32, height: u32) -> anyhow::Result<Self> {
+        Ok(OffscreenRenderer {
+            presenter: Presenter::new_offscreen(context.clone(), width, height)?,
+            converter: YuvToRgbaConverter::new(context.clone()),
+            uploader: Uploader::new(context.clone()),
+            context,
+            texture: None,
+        })
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) -> anyhow::Result<()> {
+        self.presenter.resize(width, height)
+    }
+
+    /// Selects how YUV frames are converted to RGBA on the GPU.
+    pub fn set_conversion_method(&mut self, method: ConversionMethod) {
+        self.converter.set_method(method);
+    }
+
+    /// Selects how frames are scaled into the output size.
+    pub fn set_scale_mode(&mut self, mode: ScaleMode) {
+        self.presenter.set_scale_mode(mode);
+    }
+
+    /// Selects the filter frames are scaled with.
+    pub fn set_scale_filter(&mut self, filter: ScaleFilter) {
+        self.presenter.set_filter(filter);
+    }
+
+    /// Sets the rotation and flips applied to the frames.
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.presenter.set_orientation(orientation);
+    }
+
+    /// Restricts the frames to `crop`, in pixels of their visible area.
+    pub fn set_crop(&mut self, crop: Option<CropRect>) {
+        self.presenter.set_crop(crop);
+    }
+
+    /// Renders `frame`, scaled into the output size, and reads it back.
+    pub fn render(&mut self, frame: &ffmpeg::frame::Video) -> anyhow::Result<image::RgbaImage> {
+        let texture = upload_frame(
+            &self.context,
+            &mut self.converter,
+            &mut self.uploader,
+            self.texture.take(),
+            frame,
+        )?;
+        self.presenter
+            .set_sample_aspect_ratio(sample_aspect_ratio(frame));
+        let drawn = self.presenter.draw(&texture);
+        self.texture = Some(texture);
+        drawn?;
+        self.presenter.read_pixels()
+    }
+}
+
+fn is_supported_format(format: ffmpeg::format::Pixel, features: wgpu::Features) -> bool {
+    match format {
+        ffmpeg::format::Pixel::RGBA => true,
+        _ => YuvToRgbaConverter::is_supported_format(format, features),
+    }
+}
 
-        if need_reset {
+/// Width of the pixels of `frame` relative to their height, 1 if unknown.
+fn sample_aspect_ratio(frame: &ffmpeg::frame::Video) -> f32 {
+    let sar = frame.aspect_ratio();
+    if sar.numerator() > 0 && sar.denominator() > 0 {
+        f64::from(sar) as f32
+    } else {
+        1.0
+    }
+}
+
+fn update_texture(
+    context: &WgpuContext,
+    mut texture: Option<Texture>,
+    format: wgpu::TextureFormat,
+    output_usage: wgpu::TextureUsages,
+    width: u32,
+    height: u32,
+) -> anyhow::Result<Texture> {
+    if let Some(tex) = &texture {
+        let tex_size = tex.texture.size();
+        if (width, height) != (tex_size.width, tex_size.height)
+            || tex.texture.format() != format
+            || !tex.texture.usage().contains(output_usage)
+        {
             texture = None;
         }
+    }
 
-        if texture.is_some() {
-            texture
-        } else {
-            Some(Texture::new_rgba(&context.device, width, height).unwrap())
-        }
+    match texture {
+        Some(texture) => Ok(texture),
+        None => Texture::new_frame(&context.device, format, output_usage, width, height),
     }
+}
 
-    fn thread_loop(
-        receiver: VideoReceiver,
-        sender: VideoSender,
-        request_redraw: Box<dyn Fn() + Send>,
-    ) {
-        loop {
-            if let Ok(mut frame) = receiver.recv() {
-                if !Self::is_supported_format(frame.format()) {
-                    let mut scaler = scaling::Context::get(
-                        frame.format(),
-                        frame.width(),
-                        frame.height(),
-                        ffmpeg::util::format::Pixel::RGBA,
-                        frame.width(),
-                        frame.height(),
-                        scaling::Flags::BILINEAR,
-                    )
-                    .unwrap();
-
-                    let mut rgb_frame = ffmpeg::frame::Video::empty();
-                    scaler.run(&frame, &mut rgb_frame).unwrap();
-                    frame = rgb_frame;
-                }
-                sender.send(frame).unwrap();
-                request_redraw();
-            }
+/// Uploads the visible area of `frame` to `texture`, which is recreated if
+/// its size does not match.
+fn upload_frame(
+    context: &WgpuContext,
+    converter: &mut YuvToRgbaConverter,
+    uploader: &mut Uploader,
+    texture: Option<Texture>,
+    frame: &ffmpeg::frame::Video,
+) -> anyhow::Result<Texture> {
+    // Formats the GPU does not support are converted to RGBA on the CPU.
+    let converted = !is_supported_format(frame.format(), context.device.features());
+    let format = match frame.format() {
+        _ if converted => wgpu::TextureFormat::Rgba8Unorm,
+        ffmpeg::format::Pixel::RGBA => wgpu::TextureFormat::Rgba8Unorm,
+        format => converter.output_format(format),
+    };
+    let (x, y, width, height) = crate::ffmpeg::visible_rect(frame);
+    let mut texture = update_texture(
+        context,
+        texture,
+        format,
+        converter.output_usage(),
+        width,
+        height,
+    )?;
+    texture.generation += 1;
+    match frame.format() {
+        _ if converted => {
+            let mut encoder = context
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            upload_converted(uploader, &mut encoder, frame, &texture, (x, y))?;
+            context.queue.submit(Some(encoder.finish()));
+            uploader.recall();
         }
+        ffmpeg::format::Pixel::RGBA => {
+            let mut encoder = context
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            let offset = y as usize * frame.stride(0) + x as usize * 4;
+            uploader.upload(
+                &mut encoder,
+                &[Plane {
+                    texture: &texture.texture,
+                    data: &frame.data(0)[offset..],
+                    pitch: frame.stride(0),
+                }],
+            )?;
+            context.queue.submit(Some(encoder.finish()));
+            uploader.recall();
+        }
+        _ => converter.convert(uploader, frame, &texture)?,
     }
+    Ok(texture)
+}
+
+/// Converts `frame` to RGBA on the CPU, straight into the staging buffer of
+/// its upload, and records the copy of its area at `origin`, of the size of
+/// `texture`, to `texture`.
+fn upload_converted(
+    uploader: &mut Uploader,
+    encoder: &mut wgpu::CommandEncoder,
+    frame: &ffmpeg::frame::Video,
+    texture: &Texture,
+    origin: (u32, u32),
+) -> anyhow::Result<()> {
+    let mut scaler = scaling::Context::get(
+        frame.format(),
+        frame.width(),
+        frame.height(),
+        ffmpeg::util::format::Pixel::RGBA,
+        frame.width(),
+        frame.height(),
+        scaling::Flags::BILINEAR,
+    )?;
+    uploader.upload_with(
+        encoder,
+        &texture.texture,
+        frame.width() as usize * 4,
+        frame.height() as usize,
+        origin,
+        |data, pitch| {
+            let dst = [
+                data.as_mut_ptr(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            ];
+            let dst_stride = [pitch as c_int, 0, 0, 0];
+            let result = unsafe {
+                ffmpeg::ffi::sws_scale(
+                    scaler.as_mut_ptr(),
+                    (*frame.as_ptr()).data.as_ptr() as *const *const _,
+                    (*frame.as_ptr()).linesize.as_ptr() as *const _,
+                    0,
+                    frame.height() as c_int,
+                    dst.as_ptr(),
+                    dst_stride.as_ptr(),
+                )
+            };
+            if result < 0 {
+                return Err(ffmpeg::Error::from(result).into());
+            }
+            Ok(())
+        },
+    )
 }
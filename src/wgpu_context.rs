@@ -1,75 +1,150 @@
-#[allow(dead_code)]
-pub(crate) struct WgpuContext {
+use anyhow::{anyhow, ensure, Result};
+
+/// The wgpu device used to convert and present the frames.
+pub struct WgpuContext {
     pub adapter: wgpu::Adapter,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
 }
 
 impl WgpuContext {
-    pub fn new(instance: &wgpu::Instance, surface: &wgpu::Surface<'_>) -> Self {
-        pollster::block_on(Self::new_async(instance, surface))
+    /// Creates a context able to present to `surface`.
+    pub fn new(instance: &wgpu::Instance, surface: &wgpu::Surface<'_>) -> Result<Self> {
+        pollster::block_on(Self::new_async(
+            instance,
+            Some(surface),
+            false,
+            wgpu::Limits::default(),
+        ))
+    }
+
+    /// Creates a context without any window, to convert and render frames
+    /// offscreen. With `force_fallback_adapter`, a software adapter is used,
+    /// e.g. on CI machines with no GPU.
+    pub fn new_headless(force_fallback_adapter: bool) -> Result<Self> {
+        let instance = wgpu::Instance::default();
+        // Software adapters may not reach the default limits.
+        pollster::block_on(Self::new_async(
+            &instance,
+            None,
+            force_fallback_adapter,
+            wgpu::Limits::downlevel_defaults(),
+        ))
     }
 
-    pub async fn new_async(instance: &wgpu::Instance, surface: &wgpu::Surface<'_>) -> Self {
+    async fn new_async(
+        instance: &wgpu::Instance,
+        compatible_surface: Option<&wgpu::Surface<'_>>,
+        force_fallback_adapter: bool,
+        limits: wgpu::Limits,
+    ) -> Result<Self> {
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
                 power_preference: wgpu::PowerPreference::default(),
-                force_fallback_adapter: false,
-                compatible_surface: Some(&surface),
+                force_fallback_adapter,
+                compatible_surface,
             })
             .await
-            .expect("Request adapter failed");
+            .ok_or_else(|| anyhow!("No suitable graphics adapter found"))?;
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
-                    required_features: wgpu::Features::empty(),
-                    required_limits: wgpu::Limits::default(),
+                    // Used for the planes and the converted textures of high
+                    // bit depth frames, which may be rendered to when the
+                    // adapter allows it.
+                    required_features: adapter.features()
+                        & (wgpu::Features::TEXTURE_FORMAT_16BIT_NORM
+                            | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES),
+                    required_limits: limits.using_resolution(adapter.limits()),
                     memory_hints: wgpu::MemoryHints::default(),
                 },
                 None,
             )
-            .await
-            .expect("Request device failed");
+            .await?;
 
-        WgpuContext {
+        Ok(WgpuContext {
             adapter,
             device,
             queue,
+        })
+    }
+
+    /// Usages allowed for textures of `format` on this device.
+    pub fn format_usages(&self, format: wgpu::TextureFormat) -> wgpu::TextureUsages {
+        let features = self.device.features();
+        if features.contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) {
+            self.adapter
+                .get_texture_format_features(format)
+                .allowed_usages
+        } else {
+            format.guaranteed_format_features(features).allowed_usages
         }
     }
 
-    pub fn write_texture(
-        &self,
-        texture: &wgpu::Texture,
-        data: &[u8],
-        pitch: u32,
-        width: u32,
-        height: u32,
-    ) {
-        assert!(data.len() >= (pitch * height) as usize);
+    /// Reads an RGBA texture back to the CPU. The texture must have been
+    /// created with `COPY_SRC` usage.
+    pub fn read_texture(&self, texture: &wgpu::Texture) -> Result<image::RgbaImage> {
+        ensure!(
+            texture.format() == wgpu::TextureFormat::Rgba8Unorm,
+            "Unsupported texture format: {:?}",
+            texture.format()
+        );
         let size = texture.size();
-        assert_eq!(size.width, width);
-        assert_eq!(size.height, height);
+        let row_size = size.width * 4;
+        // Rows of a buffer copy are aligned, the padding is removed below.
+        let padded_row_size = row_size.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
 
-        self.queue.write_texture(
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: padded_row_size as u64 * size.height as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_buffer(
             wgpu::ImageCopyTexture {
                 aspect: wgpu::TextureAspect::All,
                 texture,
                 mip_level: 0,
                 origin: wgpu::Origin3d::ZERO,
             },
-            data,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(pitch),
-                rows_per_image: Some(height),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_size),
+                    rows_per_image: Some(size.height),
+                },
             },
             wgpu::Extent3d {
-                width,
-                height,
+                width: size.width,
+                height: size.height,
                 depth_or_array_layers: 1,
             },
         );
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        let mut pixels = Vec::with_capacity((row_size * size.height) as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(padded_row_size as usize) {
+                pixels.extend_from_slice(&row[..row_size as usize]);
+            }
+        }
+        buffer.unmap();
+        image::RgbaImage::from_raw(size.width, size.height, pixels)
+            .ok_or_else(|| anyhow!("Texture readback failed"))
     }
 }
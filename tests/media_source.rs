@@ -0,0 +1,215 @@
+use std::{
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    net::TcpListener,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use vtk::{
+    cancellation::CancellationToken, demuxer::Demuxer, MediaError, MediaSource, MediaType,
+    OpenOptions,
+};
+
+/// A mono 16-bit PCM WAV file of `frames` silent samples at 8 kHz.
+fn wav_bytes(frames: u32) -> Vec<u8> {
+    let data_size = frames * 2;
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_size).to_le_bytes());
+    bytes.extend_from_slice(b"WAVE");
+    bytes.extend_from_slice(b"fmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // Channels
+    bytes.extend_from_slice(&8000u32.to_le_bytes()); // Sample rate
+    bytes.extend_from_slice(&16000u32.to_le_bytes()); // Byte rate
+    bytes.extend_from_slice(&2u16.to_le_bytes()); // Block align
+    bytes.extend_from_slice(&16u16.to_le_bytes()); // Bits per sample
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_size.to_le_bytes());
+    bytes.resize(bytes.len() + data_size as usize, 0);
+    bytes
+}
+
+fn check_wav_source(source: &MediaSource) {
+    assert_eq!(source.format().name(), "wav");
+    let stream = source.best_stream(MediaType::Audio).unwrap();
+    let audio = stream.audio().unwrap();
+    assert_eq!(audio.sample_rate(), 8000);
+    assert_eq!(audio.channels(), 1);
+    let duration = source.duration().unwrap();
+    assert!(duration.abs_diff(Duration::from_millis(500)) < Duration::from_millis(10));
+}
+
+#[test]
+fn open_from_bytes() {
+    vtk::init();
+    let source = MediaSource::from_bytes(wav_bytes(4000)).unwrap();
+    check_wav_source(&source);
+}
+
+#[test]
+fn open_from_reader() {
+    vtk::init();
+    let source = MediaSource::from_reader(io::Cursor::new(wav_bytes(4000))).unwrap();
+    check_wav_source(&source);
+}
+
+#[test]
+fn open_from_invalid_bytes() {
+    vtk::init();
+    assert!(MediaSource::from_bytes(vec![0u8; 64]).is_err());
+}
+
+/// Serves `body` over HTTP on a loopback port, at any path, to any number
+/// of requests. Returns the URL of the server.
+fn serve(body: Vec<u8>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for connection in listener.incoming() {
+            let Ok(mut connection) = connection else {
+                continue;
+            };
+            // The request is ignored, up to the blank line ending it.
+            let mut reader = BufReader::new(&connection);
+            let mut line = String::new();
+            while reader.read_line(&mut line).is_ok_and(|n| n > 0) && line != "\r\n" {
+                line.clear();
+            }
+            let header = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            let _ = connection
+                .write_all(header.as_bytes())
+                .and_then(|_| connection.write_all(&body));
+        }
+    });
+    url
+}
+
+#[test]
+fn open_url() {
+    vtk::init();
+    let url = serve(wav_bytes(4000));
+    let source = OpenOptions::new()
+        .probe_size(4096)
+        .analyze_duration(Duration::from_secs(1))
+        .timeout(Duration::from_secs(5))
+        .open_url(&format!("{}/media.wav", url))
+        .unwrap();
+    check_wav_source(&source);
+}
+
+#[test]
+fn open_url_with_forced_format() {
+    vtk::init();
+    // Raw samples can not be probed, their format and rate must be given.
+    let url = serve(vec![0u8; 16000]);
+    let source = OpenOptions::new()
+        .format("s16le")
+        .option("sample_rate", "8000")
+        .open_url(&format!("{}/media.raw", url))
+        .unwrap();
+    assert_eq!(source.format().name(), "s16le");
+    let stream = source.best_stream(MediaType::Audio).unwrap();
+    assert_eq!(stream.audio().unwrap().sample_rate(), 8000);
+}
+
+#[test]
+fn open_url_with_invalid_option() {
+    vtk::init();
+    let url = serve(wav_bytes(4000));
+    let result = OpenOptions::new()
+        .option("probesize", "not a number")
+        .open_url(&format!("{}/media.wav", url));
+    assert!(result.is_err());
+    let result = OpenOptions::new()
+        .format("no such format")
+        .open_url(&format!("{}/media.wav", url));
+    assert_eq!(result.err(), Some(MediaError::UnsupportedFormat));
+}
+
+/// Serves `data`, but reads from `block_at` on, short of the end, block
+/// until `token` is cancelled, like a stalled network stream.
+struct BlockingReader {
+    data: io::Cursor<Vec<u8>>,
+    block_at: u64,
+    token: CancellationToken,
+    blocked: Arc<AtomicBool>,
+}
+
+impl Read for BlockingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let position = self.data.position();
+        if position >= self.block_at && position < self.data.get_ref().len() as u64 {
+            self.blocked.store(true, Ordering::Relaxed);
+            while !self.token.is_cancelled() {
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+        self.data.read(buf)
+    }
+}
+
+impl Seek for BlockingReader {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        self.data.seek(position)
+    }
+}
+
+#[test]
+fn cancel_blocked_read() {
+    vtk::init();
+    let token = CancellationToken::new();
+    let blocked = Arc::new(AtomicBool::new(false));
+    let reader = BlockingReader {
+        data: io::Cursor::new(wav_bytes(400_000)),
+        block_at: 256 * 1024,
+        token: token.clone(),
+        blocked: blocked.clone(),
+    };
+    let canceller = {
+        let token = token.clone();
+        let blocked = blocked.clone();
+        thread::spawn(move || {
+            while !blocked.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            token.cancel();
+        })
+    };
+
+    // Reads the source to the end, unless the reads are cancelled.
+    let result = (|| -> Result<(), MediaError> {
+        let source = OpenOptions::new()
+            .cancellation_token(token)
+            .open_reader(reader)?;
+        let index = source.best_stream(MediaType::Audio).unwrap().index();
+        let demuxer = Demuxer::new(source);
+        let stream = demuxer.stream(index).unwrap();
+        while stream.read()?.is_some() {}
+        Ok(())
+    })();
+    canceller.join().unwrap();
+    assert_eq!(result, Err(MediaError::Cancelled));
+}
+
+#[test]
+fn operation_timeout() {
+    vtk::init();
+    // Accepts connections, but never answers.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/media.wav", listener.local_addr().unwrap());
+    let start = Instant::now();
+    let result = OpenOptions::new()
+        .operation_timeout(Duration::from_millis(200))
+        .open_url(&url);
+    assert_eq!(result.err(), Some(MediaError::TimedOut));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
@@ -0,0 +1,452 @@
+//! Checks the GPU YUV to RGBA conversion against a CPU reference.
+//!
+//! Synthetic frames (color bars and ramps) are generated in every supported
+//! planar and semi-planar format, bit depth and color matrix, converted on a
+//! software adapter with both conversion methods, and compared pixel by
+//! pixel. The images of failing cases are written to the directory given by
+//! `VTK_TEST_OUTPUT`, by default `yuv_conversion_test` in the temporary
+//! directory. Set `VTK_TEST_HARDWARE` to convert on the GPU of the machine
+//! instead of the software adapter.
+//!
+//! The tests are skipped when no adapter is found.
+
+use ffmpeg_next::{
+    color::{Range, Space},
+    format::Pixel,
+    frame,
+};
+use std::{env, path::PathBuf, rc::Rc};
+use vtk::{
+    video_renderer::{ConversionMethod, OffscreenRenderer},
+    wgpu_context::WgpuContext,
+};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+// Maximum difference per channel of the output, in 8-bit code values.
+const TOLERANCE: u8 = 3;
+
+/// Storage of the samples of a format, see `layout`.
+#[derive(Clone, Copy, PartialEq)]
+enum Chroma {
+    Planar,
+    Uv,
+    Vu,
+}
+
+/// Chroma storage, significant bits and left shift of the samples.
+fn layout(format: Pixel) -> (Chroma, u32, u32) {
+    match format {
+        Pixel::NV12 => (Chroma::Uv, 8, 0),
+        Pixel::NV21 => (Chroma::Vu, 8, 0),
+        Pixel::P010LE => (Chroma::Uv, 10, 6),
+        Pixel::P016LE => (Chroma::Uv, 16, 0),
+        Pixel::YUV420P10LE | Pixel::YUV422P10LE | Pixel::YUV440P10LE | Pixel::YUV444P10LE => {
+            (Chroma::Planar, 10, 0)
+        }
+        Pixel::YUV420P12LE | Pixel::YUV422P12LE | Pixel::YUV440P12LE | Pixel::YUV444P12LE => {
+            (Chroma::Planar, 12, 0)
+        }
+        Pixel::YUV420P16LE | Pixel::YUV422P16LE | Pixel::YUV444P16LE => (Chroma::Planar, 16, 0),
+        _ => (Chroma::Planar, 8, 0),
+    }
+}
+
+const RANGES: &[Range] = &[Range::MPEG, Range::JPEG, Range::Unspecified];
+
+// 75% color bars: white, yellow, cyan, green, magenta, red, blue, black.
+const BARS: &[[f32; 3]] = &[
+    [0.75, 0.75, 0.75],
+    [0.75, 0.75, 0.0],
+    [0.0, 0.75, 0.75],
+    [0.0, 0.75, 0.0],
+    [0.75, 0.0, 0.75],
+    [0.75, 0.0, 0.0],
+    [0.0, 0.0, 0.75],
+    [0.0, 0.0, 0.0],
+];
+
+#[derive(Clone, Copy, Debug)]
+enum Pattern {
+    ColorBars,
+    Ramps,
+}
+
+/// The YUV to RGB conversion expected for a frame, on samples of `bits`
+/// bits.
+#[derive(Clone, Copy)]
+struct Matrix {
+    kr: f32,
+    kb: f32,
+    full_range: bool,
+    bits: u32,
+}
+
+impl Matrix {
+    /// Mirrors the choice of the converter: BT.709 limited range unless
+    /// told otherwise, full range for the JPEG formats.
+    fn of(format: Pixel, space: Space, range: Range) -> Self {
+        let full_range = match range {
+            Range::JPEG => true,
+            Range::Unspecified => matches!(
+                format,
+                Pixel::YUVJ411P
+                    | Pixel::YUVJ420P
+                    | Pixel::YUVJ422P
+                    | Pixel::YUVJ440P
+                    | Pixel::YUVJ444P
+            ),
+            _ => false,
+        };
+        let (kr, kb) = match space {
+            Space::BT470BG | Space::SMPTE170M => (0.299, 0.114),
+            Space::BT709 => (0.2126, 0.0722),
+            Space::BT2020CL | Space::BT2020NCL => (0.2627, 0.0593),
+            _ if full_range => (0.299, 0.114),
+            _ => (0.2126, 0.0722),
+        };
+        Matrix {
+            kr,
+            kb,
+            full_range,
+            bits: layout(format).1,
+        }
+    }
+
+    fn kg(&self) -> f32 {
+        1.0 - self.kr - self.kb
+    }
+
+    fn max(&self) -> f32 {
+        ((1u32 << self.bits) - 1) as f32
+    }
+
+    /// Offset and scale of the luma and of the chroma samples.
+    fn quantization(&self) -> (f32, f32, f32, f32) {
+        let center = (1u32 << (self.bits - 1)) as f32;
+        if self.full_range {
+            (0.0, self.max(), center, self.max())
+        } else {
+            let depth = (1u32 << (self.bits - 8)) as f32;
+            (16.0 * depth, 219.0 * depth, center, 224.0 * depth)
+        }
+    }
+
+    fn rgb_to_yuv(&self, [r, g, b]: [f32; 3]) -> [u16; 3] {
+        let y = self.kr * r + self.kg() * g + self.kb * b;
+        let pb = (b - y) / (2.0 * (1.0 - self.kb));
+        let pr = (r - y) / (2.0 * (1.0 - self.kr));
+        let (y_offset, y_scale, c_offset, c_scale) = self.quantization();
+        [
+            y_offset + y * y_scale,
+            c_offset + pb * c_scale,
+            c_offset + pr * c_scale,
+        ]
+        .map(|v| v.round().clamp(0.0, self.max()) as u16)
+    }
+
+    fn yuv_to_rgb(&self, [y, u, v]: [f32; 3]) -> [u8; 3] {
+        let (y_offset, y_scale, c_offset, c_scale) = self.quantization();
+        let y = (y - y_offset) / y_scale;
+        let pb = (u - c_offset) / c_scale;
+        let pr = (v - c_offset) / c_scale;
+        let r = y + 2.0 * (1.0 - self.kr) * pr;
+        let b = y + 2.0 * (1.0 - self.kb) * pb;
+        let g = (y - self.kr * r - self.kb * b) / self.kg();
+        [r, g, b].map(|c| (c * 255.0).round().clamp(0.0, 255.0) as u8)
+    }
+}
+
+/// Where a component is stored: plane, index within a pixel of the plane
+/// and number of components per pixel of the plane.
+fn component_location(chroma: Chroma, component: usize) -> (usize, usize, usize) {
+    match (chroma, component) {
+        (_, 0) => (0, 0, 1),
+        (Chroma::Planar, c) => (c, 0, 1),
+        (Chroma::Uv, c) => (1, c - 1, 2),
+        (Chroma::Vu, c) => (1, 2 - c, 2),
+    }
+}
+
+/// Byte offset of a component of the pixel (x, y) of a plane.
+fn sample_offset(frame: &frame::Video, component: usize, x: u32, y: u32) -> (usize, usize) {
+    let (chroma, bits, _) = layout(frame.format());
+    let (plane, index, count) = component_location(chroma, component);
+    let bytes = if bits > 8 { 2 } else { 1 };
+    let offset = y as usize * frame.stride(plane) + (x as usize * count + index) * bytes;
+    (plane, offset)
+}
+
+fn read_sample(frame: &frame::Video, component: usize, x: u32, y: u32) -> u16 {
+    let (_, bits, shift) = layout(frame.format());
+    let (plane, offset) = sample_offset(frame, component, x, y);
+    let data = frame.data(plane);
+    if bits > 8 {
+        u16::from_le_bytes([data[offset], data[offset + 1]]) >> shift
+    } else {
+        data[offset] as u16
+    }
+}
+
+fn write_sample(frame: &mut frame::Video, component: usize, x: u32, y: u32, value: u16) {
+    let (_, bits, shift) = layout(frame.format());
+    let (plane, offset) = sample_offset(frame, component, x, y);
+    let data = frame.data_mut(plane);
+    if bits > 8 {
+        data[offset..offset + 2].copy_from_slice(&(value << shift).to_le_bytes());
+    } else {
+        data[offset] = value as u8;
+    }
+}
+
+/// Size of the plane holding a component.
+fn component_size(frame: &frame::Video, component: usize) -> (u32, u32) {
+    let (chroma, _, _) = layout(frame.format());
+    let (plane, _, _) = component_location(chroma, component);
+    (frame.plane_width(plane), frame.plane_height(plane))
+}
+
+/// Generates the pattern at full resolution, then subsamples the chroma by
+/// averaging.
+fn make_frame(format: Pixel, space: Space, range: Range, pattern: Pattern) -> frame::Video {
+    let matrix = Matrix::of(format, space, range);
+    let max = matrix.max() as u32;
+    let yuv_at = |x: u32, y: u32| -> [u16; 3] {
+        match pattern {
+            Pattern::ColorBars => {
+                let bar = (x * BARS.len() as u32 / WIDTH) as usize;
+                matrix.rgb_to_yuv(BARS[bar])
+            }
+            Pattern::Ramps => [
+                (x * max / (WIDTH - 1)) as u16,
+                (y * max / (HEIGHT - 1)) as u16,
+                ((x + y) * max / (WIDTH + HEIGHT - 2)) as u16,
+            ],
+        }
+    };
+
+    let mut frame = frame::Video::new(format, WIDTH, HEIGHT);
+    frame.set_color_space(space);
+    frame.set_color_range(range);
+    for component in 0..3 {
+        let (width, height) = component_size(&frame, component);
+        let (sx, sy) = (WIDTH / width, HEIGHT / height);
+        for py in 0..height {
+            for px in 0..width {
+                let mut sum = 0u32;
+                for y in py * sy..(py + 1) * sy {
+                    for x in px * sx..(px + 1) * sx {
+                        sum += yuv_at(x, y)[component] as u32;
+                    }
+                }
+                let count = sx * sy;
+                let value = ((sum + count / 2) / count) as u16;
+                write_sample(&mut frame, component, px, py, value);
+            }
+        }
+    }
+    frame
+}
+
+/// Bilinear sample of a component at the position of the luma sample
+/// (x, y), with the same sample siting and edge clamping as the GPU sampler.
+fn sample_component(frame: &frame::Video, component: usize, x: u32, y: u32) -> f32 {
+    let (width, height) = component_size(frame, component);
+    let at = |x: i64, y: i64| -> f32 {
+        let x = x.clamp(0, width as i64 - 1) as u32;
+        let y = y.clamp(0, height as i64 - 1) as u32;
+        read_sample(frame, component, x, y) as f32
+    };
+
+    let fx = (x as f32 + 0.5) * width as f32 / WIDTH as f32 - 0.5;
+    let fy = (y as f32 + 0.5) * height as f32 / HEIGHT as f32 - 0.5;
+    let (x0, y0) = (fx.floor(), fy.floor());
+    let (tx, ty) = (fx - x0, fy - y0);
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let top = at(x0, y0) * (1.0 - tx) + at(x0 + 1, y0) * tx;
+    let bottom = at(x0, y0 + 1) * (1.0 - tx) + at(x0 + 1, y0 + 1) * tx;
+    top * (1.0 - ty) + bottom * ty
+}
+
+fn reference(frame: &frame::Video) -> image::RgbaImage {
+    let matrix = Matrix::of(frame.format(), frame.color_space(), frame.color_range());
+    image::RgbaImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        let yuv = [0, 1, 2].map(|component| sample_component(frame, component, x, y));
+        let [r, g, b] = matrix.yuv_to_rgb(yuv);
+        image::Rgba([r, g, b, 255])
+    })
+}
+
+fn max_difference(a: &image::RgbaImage, b: &image::RgbaImage) -> u8 {
+    a.pixels()
+        .zip(b.pixels())
+        .flat_map(|(a, b)| a.0.into_iter().zip(b.0).map(|(a, b)| a.abs_diff(b)))
+        .max()
+        .unwrap_or(0)
+}
+
+fn output_dir() -> PathBuf {
+    match env::var_os("VTK_TEST_OUTPUT") {
+        Some(dir) => PathBuf::from(dir),
+        None => env::temp_dir().join("yuv_conversion_test"),
+    }
+}
+
+/// Creates the context to convert on, or `None` if there is no adapter.
+fn headless_context() -> Option<Rc<WgpuContext>> {
+    let hardware = env::var_os("VTK_TEST_HARDWARE").is_some();
+    match WgpuContext::new_headless(!hardware) {
+        Ok(context) => Some(Rc::new(context)),
+        Err(e) => {
+            eprintln!("Skipped, no graphics adapter: {}", e);
+            None
+        }
+    }
+}
+
+/// Converts both patterns of `format` in every range of `space`, with both
+/// conversion methods, and compares them with the CPU reference.
+fn check_conversion(format: Pixel, space: Space) {
+    let Some(context) = headless_context() else {
+        return;
+    };
+    let features = context.device.features();
+    if layout(format).1 > 8 && !features.contains(wgpu::Features::TEXTURE_FORMAT_16BIT_NORM) {
+        eprintln!("Skipped {:?}: no 16-bit normalized textures", format);
+        return;
+    }
+
+    let output_dir = output_dir();
+    let mut failures = Vec::new();
+    for method in [ConversionMethod::RenderPass, ConversionMethod::Compute] {
+        let mut renderer = OffscreenRenderer::new(context.clone(), WIDTH, HEIGHT).unwrap();
+        renderer.set_conversion_method(method);
+        for &range in RANGES {
+            for pattern in [Pattern::ColorBars, Pattern::Ramps] {
+                let name = format!(
+                    "{:?}_{:?}_{:?}_{:?}_{:?}",
+                    format, space, range, pattern, method
+                );
+                let frame = make_frame(format, space, range, pattern);
+                let expected = reference(&frame);
+                let actual = match renderer.render(&frame) {
+                    Ok(image) => image,
+                    Err(e) => {
+                        failures.push(format!("{}: {}", name, e));
+                        continue;
+                    }
+                };
+
+                let difference = max_difference(&expected, &actual);
+                if difference > TOLERANCE {
+                    failures.push(format!("{}: max difference {}", name, difference));
+                    if std::fs::create_dir_all(&output_dir).is_ok() {
+                        let _ = expected.save(output_dir.join(format!("{}_expected.png", name)));
+                        let _ = actual.save(output_dir.join(format!("{}_actual.png", name)));
+                    }
+                }
+            }
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "Conversions failed, images written to {}:\n{}",
+        output_dir.display(),
+        failures.join("\n")
+    );
+}
+
+/// Frames whose decoder left crop borders are converted without them.
+#[test]
+fn crop_borders() {
+    let Some(context) = headless_context() else {
+        return;
+    };
+    let (left, right, top, bottom) = (8, 4, 6, 2);
+    let (width, height) = (WIDTH - left - right, HEIGHT - top - bottom);
+    let mut frame = make_frame(Pixel::YUV444P, Space::BT709, Range::MPEG, Pattern::Ramps);
+    unsafe {
+        let frame = &mut *frame.as_mut_ptr();
+        frame.crop_left = left as usize;
+        frame.crop_right = right as usize;
+        frame.crop_top = top as usize;
+        frame.crop_bottom = bottom as usize;
+    }
+    let expected =
+        image::imageops::crop_imm(&reference(&frame), left, top, width, height).to_image();
+
+    for method in [ConversionMethod::RenderPass, ConversionMethod::Compute] {
+        let mut renderer = OffscreenRenderer::new(context.clone(), width, height).unwrap();
+        renderer.set_conversion_method(method);
+        let actual = renderer.render(&frame).unwrap();
+        assert_eq!(actual.dimensions(), (width, height));
+        let difference = max_difference(&expected, &actual);
+        assert!(
+            difference <= TOLERANCE,
+            "{:?}: max difference {}",
+            method,
+            difference
+        );
+    }
+}
+
+/// Declares a module of tests for each format, one test per color matrix.
+macro_rules! conversion_tests {
+    ($($name:ident: $format:expr,)*) => {
+        $(
+            mod $name {
+                use super::*;
+
+                #[test]
+                fn bt601() {
+                    check_conversion($format, Space::SMPTE170M);
+                }
+
+                #[test]
+                fn bt709() {
+                    check_conversion($format, Space::BT709);
+                }
+
+                #[test]
+                fn bt2020() {
+                    check_conversion($format, Space::BT2020NCL);
+                }
+
+                #[test]
+                fn unspecified() {
+                    check_conversion($format, Space::Unspecified);
+                }
+            }
+        )*
+    };
+}
+
+conversion_tests! {
+    yuv410p: Pixel::YUV410P,
+    yuv411p: Pixel::YUV411P,
+    yuvj411p: Pixel::YUVJ411P,
+    yuv420p: Pixel::YUV420P,
+    yuvj420p: Pixel::YUVJ420P,
+    yuv422p: Pixel::YUV422P,
+    yuvj422p: Pixel::YUVJ422P,
+    yuv440p: Pixel::YUV440P,
+    yuvj440p: Pixel::YUVJ440P,
+    yuv444p: Pixel::YUV444P,
+    yuvj444p: Pixel::YUVJ444P,
+    yuv420p10le: Pixel::YUV420P10LE,
+    yuv422p10le: Pixel::YUV422P10LE,
+    yuv440p10le: Pixel::YUV440P10LE,
+    yuv444p10le: Pixel::YUV444P10LE,
+    yuv420p12le: Pixel::YUV420P12LE,
+    yuv422p12le: Pixel::YUV422P12LE,
+    yuv440p12le: Pixel::YUV440P12LE,
+    yuv444p12le: Pixel::YUV444P12LE,
+    yuv420p16le: Pixel::YUV420P16LE,
+    yuv422p16le: Pixel::YUV422P16LE,
+    yuv444p16le: Pixel::YUV444P16LE,
+    nv12: Pixel::NV12,
+    nv21: Pixel::NV21,
+    p010le: Pixel::P010LE,
+    p016le: Pixel::P016LE,
+}